@@ -0,0 +1,243 @@
+use crate::exiftool::{ExiftoolTags, TagReader};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+// Bumped whenever the layout changes, older cache files are ignored (and replaced) then
+const CACHE_VERSION: u64 = 1;
+
+// What exiftool read from a file, along with the size and modification time it had then
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    size: u64,
+    // Nanoseconds since the Unix epoch
+    modified: u64,
+    // Map<tag, value>, the value is empty if the file doesn't have the tag
+    tags: HashMap<String, String>,
+}
+
+// Remembers what exiftool read from every file between runs with "--cache", as running it is by far the slowest part.
+// Entries are keyed by the canonical path, and are only used as long as the size and modification time still match.
+#[derive(Debug, Default)]
+pub struct ExiftoolCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, CacheEntry>>,
+}
+
+// "$XDG_CACHE_HOME/ios2exif/exiftool.json", falling back to "~/.cache/ios2exif/exiftool.json"
+// On Windows it's "%LOCALAPPDATA%\ios2exif\exiftool.json" instead.
+pub fn cache_path() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if let Some(directory) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        PathBuf::from(directory)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".cache")
+    };
+
+    Some(directory.join("ios2exif").join("exiftool.json"))
+}
+
+// The canonical path, size and modification time of a file, or None if it can't be read
+fn fingerprint(path: &Path) -> Option<(PathBuf, u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some((
+        fs::canonicalize(path).ok()?,
+        metadata.len(),
+        u64::try_from(modified.as_nanos()).ok()?,
+    ))
+}
+
+impl ExiftoolCache {
+    // Reads the cache file, starting over with an empty cache if it doesn't exist or can't be used
+    pub fn load(path: &Path) -> Self {
+        let mut entries = HashMap::new();
+        let cache: Option<Value> = fs::read(path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok());
+
+        if let Some(files) = cache
+            .as_ref()
+            .filter(|cache| cache["version"] == CACHE_VERSION)
+            .and_then(|cache| cache["files"].as_object())
+        {
+            for (file, entry) in files {
+                let (Some(size), Some(modified), Some(tags)) = (
+                    entry["size"].as_u64(),
+                    entry["modified"].as_u64(),
+                    entry["tags"].as_object(),
+                ) else {
+                    continue;
+                };
+                let tags = tags
+                    .iter()
+                    .filter_map(|(tag, value)| Some((tag.clone(), value.as_str()?.to_string())))
+                    .collect();
+
+                entries.insert(
+                    PathBuf::from(file),
+                    CacheEntry {
+                        size,
+                        modified,
+                        tags,
+                    },
+                );
+            }
+        }
+
+        Self {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    // Writes the cache file, creating its folder if needed
+    pub fn save(&self) -> io::Result<()> {
+        let entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let files: Map<String, Value> = entries
+            .iter()
+            .map(|(file, entry)| {
+                (
+                    file.to_string_lossy().to_string(),
+                    json!({
+                        "size": entry.size,
+                        "modified": entry.modified,
+                        "tags": entry.tags,
+                    }),
+                )
+            })
+            .collect();
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        fs::write(
+            &self.path,
+            json!({ "version": CACHE_VERSION, "files": files }).to_string(),
+        )
+    }
+
+    // The value of the tag if it's cached and the file hasn't changed since, which is empty if the file doesn't have it
+    pub fn get(&self, path: &Path, tag: &str) -> Option<String> {
+        let (file, size, modified) = fingerprint(path)?;
+        let entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let entry = entries
+            .get(&file)
+            .filter(|entry| entry.size == size && entry.modified == modified)?;
+
+        entry.tags.get(tag).cloned()
+    }
+
+    // The tags the file has out of these, but only if every one of them is cached (as a batched read would've read them all)
+    pub fn get_all(&self, path: &Path, tags: &[&str]) -> Option<ExiftoolTags> {
+        let mut values = ExiftoolTags::new();
+
+        for tag in tags {
+            let value = self.get(path, tag)?;
+
+            if !value.is_empty() {
+                values.insert(tag.to_string(), value);
+            }
+        }
+
+        Some(values)
+    }
+
+    // Remembers the value of a tag (empty if the file doesn't have it), forgetting everything else if the file changed
+    pub fn insert(&self, path: &Path, tag: &str, value: String) {
+        let Some((file, size, modified)) = fingerprint(path) else {
+            return;
+        };
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let entry = entries.entry(file).or_insert_with(|| CacheEntry {
+            size,
+            modified,
+            tags: HashMap::new(),
+        });
+
+        if entry.size != size || entry.modified != modified {
+            *entry = CacheEntry {
+                size,
+                modified,
+                tags: HashMap::new(),
+            };
+        }
+
+        entry.tags.insert(tag.to_string(), value);
+    }
+
+    // Moves the entry of a file along with it, renaming doesn't change its contents (or modification time)
+    pub fn rename(&self, from: &Path, to: &Path) {
+        let Ok(to) = fs::canonicalize(to) else {
+            return;
+        };
+        // The original path doesn't exist anymore, so it can only be canonicalized through its folder
+        let Some(from) = from
+            .parent()
+            .map(|parent| {
+                if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                }
+            })
+            .and_then(|parent| fs::canonicalize(parent).ok())
+            .zip(from.file_name())
+            .map(|(parent, name)| parent.join(name))
+        else {
+            return;
+        };
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        if let Some(entry) = entries.remove(&from) {
+            entries.insert(to, entry);
+        }
+    }
+}
+
+// Reads the tags through the cache (if there is one), only running exiftool for those that aren't cached yet
+pub struct CachedTagReader<'a, R> {
+    pub reader: R,
+    pub cache: Option<&'a ExiftoolCache>,
+}
+
+impl<R: TagReader> TagReader for CachedTagReader<'_, R> {
+    fn read_tag(&self, tag: &str, path: &Path) -> io::Result<Vec<u8>> {
+        let Some(cache) = self.cache else {
+            return self.reader.read_tag(tag, path);
+        };
+
+        if let Some(value) = cache.get(path, tag) {
+            return Ok(value.into_bytes());
+        }
+
+        let output = self.reader.read_tag(tag, path)?;
+
+        // Values that aren't valid UTF-8 are rejected anyway, so there's no point in keeping them
+        if let Ok(value) = String::from_utf8(output.clone()) {
+            cache.insert(path, tag, value.trim_end_matches(['\r', '\n']).to_string());
+        }
+
+        Ok(output)
+    }
+}
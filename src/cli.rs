@@ -0,0 +1,321 @@
+use clap::{value_parser, Arg, ArgAction, Command};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+// The flags shared by "inspect" with renaming, which read the dates the same way
+const INSPECT_ARGUMENTS: [&str; 4] = ["exiftool-path", "no-exiftool", "exiftool-tag", "takeout"];
+// The flags shared by "undo" with renaming, which find the files the same way
+const UNDO_ARGUMENTS: [&str; 7] = [
+    "dry-run",
+    "no-dry-run",
+    "recursive",
+    "max-depth",
+    "include-hidden",
+    "follow-symlinks",
+    "force",
+];
+
+// The command line interface, which "--generate-completions" describes to the shells as well
+// Renaming is the default, so "ios2exif <PATHS>" works the same as "ios2exif rename <PATHS>".
+pub fn command() -> Command {
+    Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Renames photos and videos after the date they were taken")
+        .args_conflicts_with_subcommands(true)
+        // The last one wins if a flag is given multiple times, e.g. in an alias and then again
+        .args_override_self(true)
+        .args(global_arguments())
+        .args(rename_arguments())
+        .arg(
+            Arg::new("generate-completions")
+                .long("generate-completions")
+                .value_name("SHELL")
+                .value_parser(value_parser!(Shell))
+                .exclusive(true)
+                .hide(true)
+                .help("Print a completion script for this shell"),
+        )
+        .subcommand(
+            Command::new("rename")
+                .about("Rename the files after their timestamps (the default)")
+                .args(rename_arguments()),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Only print every date found in the files, to tell why a file got the date it got")
+                .arg(
+                    paths()
+                        .num_args(1..)
+                        .required(true)
+                        .value_name("FILES")
+                        .help("The files to print the dates of"),
+                )
+                .args(shared_arguments(&INSPECT_ARGUMENTS)),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Rename the files back to the original names recorded by \"--sidecar\"")
+                .arg(paths())
+                .args(shared_arguments(&UNDO_ARGUMENTS)),
+        )
+}
+
+fn paths() -> Arg {
+    Arg::new("paths")
+        .value_name("PATHS")
+        .value_parser(value_parser!(PathBuf))
+        .num_args(0..)
+        .help("Files and folders to process, the current working directory if none are given")
+}
+
+// Some of the rename flags for another subcommand
+fn shared_arguments(names: &[&str]) -> Vec<Arg> {
+    rename_arguments()
+        .into_iter()
+        .filter(|argument| names.contains(&argument.get_id().as_str()))
+        .collect()
+}
+
+// A flag without a value
+fn flag(name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .action(ArgAction::SetTrue)
+        .help(help)
+}
+
+// A flag followed by a value, which is parsed (and validated) along with the rest of the options
+fn option(name: &'static str, value_name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name).long(name).value_name(value_name).help(help)
+}
+
+// Output flags, which are accepted anywhere (e.g. "ios2exif undo -v")
+fn global_arguments() -> Vec<Arg> {
+    vec![
+        flag("quiet", "Only print warnings and errors")
+            .short('q')
+            .global(true)
+            .overrides_with("verbose"),
+        flag("verbose", "Also print where each timestamp came from")
+            .short('v')
+            .global(true)
+            .overrides_with("quiet"),
+        option(
+            "log-format",
+            "FORMAT",
+            "How warnings and errors are printed",
+        )
+        .value_parser(["text", "json"])
+        .global(true),
+    ]
+}
+
+fn rename_arguments() -> Vec<Arg> {
+    vec![
+        paths(),
+        flag("dry-run", "Only print what would be renamed")
+            .short('n')
+            .overrides_with("no-dry-run"),
+        flag("no-dry-run", "Rename even if the config file sets dry_run").overrides_with("dry-run"),
+        flag("recursive", "Also process files in every subdirectory").short('r'),
+        option(
+            "max-depth",
+            "N",
+            "Like --recursive, but only going N folder levels deep",
+        ),
+        flag(
+            "watch",
+            "Keep renaming the files that show up in the folders afterwards",
+        ),
+        flag(
+            "dedupe",
+            "Add a suffix to duplicate timestamps instead of aborting",
+        )
+        .overrides_with("collision-policy"),
+        option(
+            "collision-policy",
+            "POLICY",
+            "What happens when a new name is already taken",
+        )
+        .value_parser(["error", "skip", "suffix", "overwrite"])
+        .overrides_with("dedupe"),
+        option(
+            "dup-format",
+            "FORMAT",
+            "The suffix --dedupe adds, e.g. \"_{n:03}\"",
+        ),
+        flag(
+            "dedupe-content",
+            "Skip files identical to another one with the same timestamp",
+        ),
+        flag(
+            "simulate-conflicts",
+            "Only list the files that would get the same name",
+        ),
+        flag(
+            "keep-going",
+            "Rename everything that doesn't conflict, listing the conflicts at the end",
+        ),
+        flag(
+            "report-only-conflicts",
+            "Only print the conflicts, nothing else",
+        ),
+        flag("yes", "Accept all warnings without prompting").short('y'),
+        flag("no-interactive", "Never prompt, exit with an error instead"),
+        flag(
+            "default-yes",
+            "Pressing Enter at the prompt accepts the warnings",
+        )
+        .overrides_with("default-no"),
+        flag("default-no", "Pressing Enter at the prompt exits").overrides_with("default-yes"),
+        flag("interactive", "Ask before renaming each file").short('i'),
+        flag(
+            "copy",
+            "Copy files to their new names instead of renaming them",
+        ),
+        option(
+            "output-dir",
+            "DIR",
+            "Place all renamed files in this directory",
+        )
+        .value_parser(value_parser!(PathBuf)),
+        flag(
+            "flat",
+            "With --recursive, move the files found in subfolders up",
+        ),
+        flag("by-year", "Move files into year folders").overrides_with_all(["by-month", "by-day"]),
+        flag("by-month", "Move files into month folders").overrides_with_all(["by-year", "by-day"]),
+        flag("by-day", "Move files into day folders").overrides_with_all(["by-year", "by-month"]),
+        flag("prune-empty", "Remove the folders left empty").alias("prune-empty-dirs"),
+        flag("transactional", "Undo everything if a rename fails").alias("rollback-on-error"),
+        flag(
+            "sidecar",
+            "Write a JSON file next to each renamed file, which \"undo\" can use",
+        ),
+        flag("force", "Run even if another run seems to be going"),
+        option("jobs", "N", "Read up to N files at the same time"),
+        option("sort", "ORDER", "The order files are renamed and listed in").value_parser([
+            "name",
+            "timestamp",
+            "size",
+            "mtime",
+        ]),
+        flag("json", "Print a JSON report to stdout"),
+        option(
+            "format",
+            "FORMAT",
+            "strftime-like format of the new filename",
+        ),
+        option(
+            "template",
+            "TEMPLATE",
+            "What the new name is made of, e.g. \"{date}_{camera}\"",
+        ),
+        flag(
+            "template-strict",
+            "Skip the files missing a value the template uses",
+        ),
+        option("date-sep", "SEP", "The separator between the date fields"),
+        option("time-sep", "SEP", "The separator between the time fields"),
+        option(
+            "datetime-sep",
+            "SEP",
+            "The separator between the date and the time",
+        ),
+        option("prefix", "TEXT", "Add text before the timestamp"),
+        option("suffix", "TEXT", "Add text after the timestamp"),
+        flag(
+            "keep-original",
+            "Keep the original filename after the timestamp",
+        ),
+        option(
+            "strip-suffix",
+            "TEXT",
+            "Remove this text from the end of the original filename",
+        )
+        .action(ArgAction::Append),
+        flag("lowercase-ext", "Lowercase the extension (the default)")
+            .overrides_with_all(["uppercase-ext", "preserve-ext"]),
+        flag("uppercase-ext", "Uppercase the extension")
+            .overrides_with_all(["lowercase-ext", "preserve-ext"]),
+        flag("preserve-ext", "Keep the extension as it was")
+            .overrides_with_all(["lowercase-ext", "uppercase-ext"]),
+        option(
+            "tz",
+            "TZ",
+            "keep-local (the default), utc, local or an IANA name like America/Chicago",
+        ),
+        flag(
+            "utc-suffix",
+            "Keep the UTC dates of videos with a \" (utc)\" suffix",
+        ),
+        option(
+            "tag-priority",
+            "TAGS",
+            "Comma-separated tags to take the timestamp from in order",
+        ),
+        flag(
+            "prefer-gps",
+            "Take the timestamp of photos from their GPS time first",
+        ),
+        flag(
+            "prefer-video-date",
+            "Name Live Photos after the date of their video",
+        ),
+        flag(
+            "append-gps",
+            "Add the GPS coordinates of photos after the timestamp",
+        ),
+        option(
+            "exiftool-tag",
+            "TAG",
+            "Also try this exiftool tag after all the others",
+        )
+        .action(ArgAction::Append),
+        option("exiftool-path", "PATH", "The exiftool binary to use")
+            .value_parser(value_parser!(PathBuf)),
+        flag("no-exiftool", "Never run exiftool"),
+        flag("cache", "Remember what exiftool read for the next run"),
+        flag("mtime-fallback", "Fall back to the modification time"),
+        flag("takeout", "Fall back to the JSON files of Google Takeout"),
+        option(
+            "manifest",
+            "CSV",
+            "Take the timestamps from this CSV file of <file>,<epoch> lines",
+        )
+        .value_parser(value_parser!(PathBuf)),
+        option("min-year", "YEAR", "Reject timestamps before this year"),
+        option("max-year", "YEAR", "Reject timestamps after this year"),
+        option(
+            "after",
+            "DATE",
+            "Only rename files taken at or after this date",
+        ),
+        option("before", "DATE", "Only rename files taken before this date"),
+        option(
+            "only",
+            "EXTENSIONS",
+            "Only process files with these comma-separated extensions",
+        ),
+        option(
+            "exclude",
+            "EXTENSIONS",
+            "Leave files with these comma-separated extensions alone",
+        )
+        .action(ArgAction::Append),
+        option(
+            "pattern",
+            "GLOB",
+            "Only process files whose name matches this glob",
+        )
+        .action(ArgAction::Append),
+        flag("include-hidden", "Also process hidden files and folders"),
+        flag("follow-symlinks", "Also process symbolic links"),
+        flag(
+            "since-last-run",
+            "Only process the files modified since the last run",
+        ),
+        // What "inspect" used to be, which still works the same
+        flag("print-metadata", "Same as \"ios2exif inspect\"").hide(true),
+    ]
+}
@@ -0,0 +1,87 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+// Defaults from the config file, each of which is overridden by the matching command line option
+// The values are checked the same way as the options they stand in for.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub format: Option<String>,
+    pub timezone: Option<String>,
+    pub date_separator: Option<String>,
+    pub time_separator: Option<String>,
+    pub datetime_separator: Option<String>,
+    pub exiftool_path: Option<PathBuf>,
+    pub dry_run: Option<bool>,
+}
+
+// "$XDG_CONFIG_HOME/ios2exif/config.toml", falling back to "~/.config/ios2exif/config.toml"
+// On Windows it's "%APPDATA%\ios2exif\config.toml" instead.
+pub fn config_path() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if let Some(directory) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        PathBuf::from(directory)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+
+    Some(directory.join("ios2exif").join("config.toml"))
+}
+
+// Reads the config file, returning the defaults if it doesn't exist
+// Unknown keys are rejected so that typos don't go unnoticed.
+pub fn load_config(path: &Path) -> Result<Config, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(error) => {
+            return Err(format!(
+                "Error: Failed to read the config file \"{}\" - {error}",
+                path.display()
+            ))
+        }
+    };
+    let table = contents.parse::<Table>().map_err(|error| {
+        format!(
+            "Error: The config file \"{}\" isn't valid TOML - {error}",
+            path.display()
+        )
+    })?;
+    let mut config = Config::default();
+
+    for (key, value) in table {
+        let invalid = |expected: &str| {
+            format!(
+                "Error: \"{key}\" in the config file \"{}\" has to be {expected}.",
+                path.display()
+            )
+        };
+
+        match (key.as_str(), value) {
+            ("format", Value::String(value)) => config.format = Some(value),
+            ("tz", Value::String(value)) => config.timezone = Some(value),
+            ("date_sep", Value::String(value)) => config.date_separator = Some(value),
+            ("time_sep", Value::String(value)) => config.time_separator = Some(value),
+            ("datetime_sep", Value::String(value)) => config.datetime_separator = Some(value),
+            ("exiftool_path", Value::String(value)) => {
+                config.exiftool_path = Some(PathBuf::from(value))
+            }
+            ("dry_run", Value::Boolean(value)) => config.dry_run = Some(value),
+            ("format" | "tz" | "date_sep" | "time_sep" | "datetime_sep" | "exiftool_path", _) => {
+                return Err(invalid("a string"))
+            }
+            ("dry_run", _) => return Err(invalid("true or false")),
+            _ => {
+                return Err(format!(
+                    "Error: Unknown key \"{key}\" in the config file \"{}\".",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,208 @@
+use chrono::{FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// The date and time extracted from a metadata source
+pub struct DateTimeParts {
+    pub datetime: NaiveDateTime,
+    // Milliseconds as three digits (e.g. "123"), only if the source records fractional seconds
+    pub subsec: Option<String>,
+    // The UTC offset the (local) date and time were recorded at, only if the source records it
+    pub offset: Option<FixedOffset>,
+    // The value as it was read from the metadata, e.g. "2023:05:14 21:34:06-05:00"
+    pub raw: String,
+}
+
+// How timestamps are rendered if their UTC offset is known
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Timezone {
+    // Keep the local wall-clock time, as shown on the device when it was recorded
+    KeepLocal,
+    // Convert to UTC, so files recorded in different timezones sort correctly
+    Utc,
+    // Convert to the timezone of this computer
+    Local,
+    // Convert to an IANA timezone, e.g. "America/Chicago"
+    Named(Tz),
+}
+
+// Parses a bound for "--after" and "--before", either "YYYY-MM-DD" (midnight) or "YYYY-MM-DD HH:MM:SS"
+pub fn parse_date_bound(raw: &str) -> Option<NaiveDateTime> {
+    if let Ok(date) = NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d") {
+        return Some(date.and_time(NaiveTime::MIN));
+    }
+
+    parse_datetime(raw).map(|parts| parts.datetime)
+}
+
+// Parses a timestamp of format "YYYY:MM:DD HH:MM:SS" (exiftool) or "YYYY-MM-DD HH:MM:SS" (exif crate),
+// validating that every field is in range (so "0000:00:00 00:00:00" is rejected)
+// Some devices record fractional seconds in QuickTime tags as well (e.g. "21:34:06.123"), which are kept as the subsec.
+pub fn parse_datetime(raw: &str) -> Option<DateTimeParts> {
+    let (date, time) = raw.trim().split_once(' ')?;
+    let date_separator = if date.contains('-') { '-' } else { ':' };
+    let mut date = date.split(date_separator);
+    let mut time = time.split(':');
+
+    let year = date.next()?.parse().ok()?;
+    let month = date.next()?.parse().ok()?;
+    let day = date.next()?.parse().ok()?;
+    let hour = time.next()?.parse().ok()?;
+    let minute = time.next()?.parse().ok()?;
+    let second = time.next()?;
+    let (second, subsec) = match second.split_once('.') {
+        Some((second, subsec)) => (second, Some(parse_subsec(subsec)?)),
+        None => (second, None),
+    };
+    let second = second.parse().ok()?;
+
+    // Trailing fields mean it's some other format
+    if date.next().is_some() || time.next().is_some() {
+        return None;
+    }
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let time = NaiveTime::from_hms_opt(hour, minute, second)?;
+
+    Some(DateTimeParts {
+        datetime: NaiveDateTime::new(date, time),
+        subsec,
+        offset: None,
+        raw: raw.trim().to_string(),
+    })
+}
+
+// Whether a timestamp is a placeholder for an unknown date, which EXIF writes as all zeros or all spaces
+// (e.g. "0000:00:00 00:00:00" or "    :  :     :  :  "), exiftool may add a zero offset ("+00:00") too
+pub fn is_unset_datetime(raw: &str) -> bool {
+    raw.chars()
+        .all(|character| matches!(character, '0' | ' ' | ':' | '-' | '+' | '\0'))
+}
+
+// Parses a timestamp like parse_datetime, optionally followed by a UTC offset ("+HH:MM", "-HH:MM" or "Z"),
+// e.g. "2023:05:14 21:34:06-05:00" (QuickTime CreationDate) or "2023:05:14 21:34:06" if exiftool omits it
+pub fn parse_datetime_with_offset(raw: &str) -> Option<DateTimeParts> {
+    let raw = raw.trim();
+
+    if let Some(datetime) = raw.strip_suffix('Z') {
+        let mut parts = parse_datetime(datetime)?;
+        parts.offset = parse_offset("Z");
+        parts.raw = raw.to_string();
+        return Some(parts);
+    }
+
+    // Only look for a sign in the time, as the date may be separated by '-' too
+    let time_start = raw.rfind(' ')?;
+
+    match raw.rfind(['+', '-']) {
+        Some(index) if index > time_start => {
+            let mut parts = parse_datetime(&raw[..index])?;
+            parts.offset = Some(parse_offset(&raw[index..])?);
+            parts.raw = raw.to_string();
+            Some(parts)
+        }
+        _ => parse_datetime(raw),
+    }
+}
+
+// Parses an ISO 8601 timestamp as used by XMP, e.g. "2019-10-15T02:08:48",
+// optionally with fractional seconds and a UTC offset, e.g. "2019-10-15T02:08:48.123+02:00"
+pub fn parse_iso_datetime(raw: &str) -> Option<DateTimeParts> {
+    let raw = raw.trim();
+    let (date, time) = raw.split_once('T')?;
+    let (time, offset) = time.split_at(time.find(['+', '-', 'Z']).unwrap_or(time.len()));
+    let (time, subsec) = match time.split_once('.') {
+        Some((time, subsec)) => (time, Some(parse_subsec(subsec)?)),
+        None => (time, None),
+    };
+
+    let mut parts = parse_datetime(&format!("{date} {time}"))?;
+    parts.subsec = subsec;
+    parts.raw = raw.to_string();
+
+    if !offset.is_empty() {
+        parts.offset = Some(parse_offset(offset)?);
+    }
+
+    Some(parts)
+}
+
+// Normalizes the digits of a fractional second (e.g. "12" from SubSecTimeOriginal) to milliseconds ("120")
+// Returns None if there are no digits to use
+pub fn parse_subsec(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+
+    if raw.is_empty() || !raw.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(format!("{:0<3}", &raw[..raw.len().min(3)]))
+}
+
+// Parses a UTC offset of format "+HH:MM", "-HH:MM" (e.g. OffsetTimeOriginal) or "Z"
+pub fn parse_offset(raw: &str) -> Option<FixedOffset> {
+    let raw = raw.trim();
+
+    if raw == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+
+    let sign = match raw.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = raw[1..].split_once(':')?;
+
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+
+    if minutes >= 60 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+// Shifts the date and time into the requested timezone, which is a no-op if the offset isn't known
+pub fn convert_timezone(parts: DateTimeParts, timezone: Timezone) -> DateTimeParts {
+    let Some(offset) = parts.offset else {
+        return parts;
+    };
+    let utc = parts.datetime - offset;
+
+    let (datetime, offset) = match timezone {
+        Timezone::KeepLocal => return parts,
+        Timezone::Utc => in_timezone(&utc, &Utc),
+        Timezone::Local => in_timezone(&utc, &Local),
+        Timezone::Named(tz) => in_timezone(&utc, &tz),
+    };
+
+    DateTimeParts {
+        datetime,
+        offset: Some(offset),
+        ..parts
+    }
+}
+
+// Returns the local date and time in the timezone for a UTC date and time, along with the offset in effect then
+fn in_timezone<Z: TimeZone>(utc: &NaiveDateTime, timezone: &Z) -> (NaiveDateTime, FixedOffset) {
+    let converted = timezone.from_utc_datetime(utc);
+
+    (converted.naive_local(), converted.offset().fix())
+}
+
+// Renders the date and time using a strftime-like layout (which has already been validated)
+// Fractional seconds are appended as "-NNN" if present, so burst photos taken within the same second don't collide
+// Names like "%A" and "%b" are always in English regardless of the system locale, so the same file gets the same name anywhere.
+pub fn format_datetime(parts: &DateTimeParts, layout: &str) -> String {
+    let formatted = parts.datetime.format(layout).to_string();
+
+    match &parts.subsec {
+        Some(subsec) => format!("{formatted}-{subsec}"),
+        None => formatted,
+    }
+}
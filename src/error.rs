@@ -0,0 +1,150 @@
+use chrono::NaiveDateTime;
+use std::{error::Error, fmt, io};
+
+// Why a timestamp couldn't be extracted from a file, "path" is the file in question
+#[derive(Debug)]
+pub enum TimestampError {
+    // The file couldn't be opened for reading
+    FileOpen {
+        path: String,
+        source: io::Error,
+    },
+    // The file doesn't contain (valid) EXIF metadata at all
+    InvalidExif {
+        path: String,
+    },
+    // The metadata is present but doesn't include the tag
+    MissingTag {
+        path: String,
+        tag: String,
+    },
+    // The tag is present but left blank, e.g. "0000:00:00 00:00:00" by cameras whose clock was never set
+    UnsetDate {
+        path: String,
+        tag: String,
+        value: String,
+    },
+    // The tag is present but its value isn't a date that can be used
+    InvalidDate {
+        path: String,
+        tag: String,
+        value: String,
+    },
+    // The tag value returned by exiftool isn't valid UTF-8
+    Utf8 {
+        path: String,
+        tag: String,
+        bytes: Vec<u8>,
+    },
+    // The "exiftool" process couldn't be executed
+    ExiftoolFailed {
+        path: String,
+        source: io::Error,
+    },
+    // The filesystem modification time couldn't be read
+    ModifiedTime {
+        path: String,
+        source: io::Error,
+    },
+    // The timestamp is outside of "--min-year" and "--max-year", so it's most likely corrupt
+    ImplausibleYear {
+        path: String,
+        // Where the timestamp came from, e.g. "EXIF DateTimeOriginal"
+        tag: String,
+        datetime: NaiveDateTime,
+    },
+    // The timestamp is fine but outside of the range given by "--after" and "--before"
+    OutOfRange {
+        path: String,
+        datetime: NaiveDateTime,
+    },
+    // The file doesn't have a value the template uses, only with "--template-strict"
+    MissingTemplateValue {
+        path: String,
+        // E.g. "camera"
+        token: &'static str,
+    },
+}
+
+impl TimestampError {
+    // What went wrong for "--log-format json", e.g. "no_datetimeoriginal" for a missing DateTimeOriginal
+    pub fn code(&self) -> String {
+        match self {
+            Self::FileOpen { .. } => String::from("file_open_failed"),
+            Self::InvalidExif { .. } => String::from("missing_exif"),
+            Self::MissingTag { tag, .. } => format!("no_{}", tag.to_lowercase()),
+            Self::UnsetDate { .. } => String::from("unset_date"),
+            Self::InvalidDate { .. } => String::from("invalid_date"),
+            Self::Utf8 { .. } => String::from("invalid_utf8"),
+            Self::ExiftoolFailed { .. } => String::from("exiftool_failed"),
+            Self::ModifiedTime { .. } => String::from("mtime_failed"),
+            Self::ImplausibleYear { .. } => String::from("implausible_year"),
+            Self::OutOfRange { .. } => String::from("out_of_range"),
+            Self::MissingTemplateValue { .. } => String::from("missing_template_value"),
+        }
+    }
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileOpen { path, source } => {
+                write!(f, "Failed to open the file: \"{path}\" - {source}")
+            }
+            Self::InvalidExif { path } => {
+                write!(f, "The file doesn't contain valid EXIF: \"{path}\"")
+            }
+            Self::MissingTag { path, tag } => {
+                write!(f, "The metadata doesn't include {tag} for file \"{path}\"")
+            }
+            Self::UnsetDate { path, tag, value } => {
+                write!(f, "{tag} is blank (\"{value}\") for file \"{path}\"")
+            }
+            Self::InvalidDate { path, tag, value } => {
+                write!(
+                    f,
+                    "{tag} \"{value}\" isn't a valid date for file \"{path}\""
+                )
+            }
+            Self::Utf8 { path, tag, bytes } => write!(
+                f,
+                "{tag} \"{bytes:?}\" should be a valid UTF-8 string for file \"{path}\""
+            ),
+            Self::ExiftoolFailed { path, source } => write!(
+                f,
+                "Failed to execute \"exiftool\" process on path \"{path}\" - {source}"
+            ),
+            Self::ModifiedTime { path, source } => write!(
+                f,
+                "Failed to read the modification time of file \"{path}\" - {source}"
+            ),
+            Self::ImplausibleYear {
+                path,
+                tag,
+                datetime,
+            } => write!(
+                f,
+                "The {tag} {datetime} of file \"{path}\" is outside of \"--min-year\" and \"--max-year\", ignoring it"
+            ),
+            Self::OutOfRange { path, datetime } => write!(
+                f,
+                "The timestamp {datetime} of file \"{path}\" is outside of the date range"
+            ),
+            Self::MissingTemplateValue { path, token } => write!(
+                f,
+                "The file \"{path}\" doesn't have a value for \"{{{token}}}\" in the template"
+            ),
+        }
+    }
+}
+
+impl Error for TimestampError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::FileOpen { source, .. }
+            | Self::ExiftoolFailed { source, .. }
+            | Self::ModifiedTime { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
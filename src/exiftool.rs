@@ -0,0 +1,354 @@
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+// Tag values of a single file as read by exiftool, Map<tag, value>
+pub type ExiftoolTags = HashMap<String, String>;
+
+// Every tag a video might be renamed by, read up front for all of them at once
+pub const BATCH_TAGS: [&str; 5] = [
+    "CreationDate",
+    "CreateDate",
+    "MediaCreateDate",
+    "DateCreated",
+    "DateTimeOriginal",
+];
+
+// Checks that a user-provided exiftool binary exists and is executable
+pub fn validate_exiftool_path(program: &Path) -> Result<(), String> {
+    let Ok(metadata) = fs::metadata(program) else {
+        return Err(format!(
+            "Error: The exiftool binary \"{}\" doesn't exist.",
+            program.display()
+        ));
+    };
+
+    if !metadata.is_file() {
+        return Err(format!(
+            "Error: The exiftool binary \"{}\" isn't a file.",
+            program.display()
+        ));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!(
+                "Error: The exiftool binary \"{}\" isn't executable.",
+                program.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Lets exiftool read paths longer than 260 characters (MAX_PATH) on Windows, where it's off by default in older versions
+// The std::fs functions already take care of that for the renames themselves.
+fn long_path_arguments() -> &'static [&'static str] {
+    if cfg!(windows) {
+        &["-api", "WindowsLongPath=1"]
+    } else {
+        &[]
+    }
+}
+
+// How often starting exiftool is attempted before giving up, and how long to wait before the first retry (doubling every time)
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// Retries starting a process, which fails every now and then under heavy I/O (especially on Windows)
+// A missing or forbidden binary won't appear by waiting, so those fail right away.
+fn with_retries<T>(mut spawn: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut attempt = 1;
+
+    loop {
+        match spawn() {
+            Err(error)
+                if attempt < ATTEMPTS
+                    && !matches!(
+                        error.kind(),
+                        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                    ) =>
+            {
+                thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Probes for exiftool by asking for its version, which is quick and has no side effects
+pub fn is_exiftool_available(program: &Path) -> bool {
+    with_retries(|| {
+        Command::new(program)
+            .arg("-ver")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    })
+    .is_ok_and(|status| status.success())
+}
+
+// Runs exiftool a single time for all the given paths rather than once per file and tag,
+// since the process startup is what dominates the runtime for large folders.
+// Returns Map<path, tags>, where a file is missing if exiftool couldn't read it at all.
+// Paths that aren't valid UTF-8 can't be matched up with the JSON output, so the caller has to read those separately.
+pub fn read_tags_batch(
+    program: &Path,
+    paths: &[PathBuf],
+    tags: &[&str],
+) -> io::Result<HashMap<PathBuf, ExiftoolTags>> {
+    // "-j" outputs a JSON array with one object per file, "SourceFile" being the path as passed in.
+    // "-@ -" reads the paths from stdin instead, as thousands of them won't fit on the command line.
+    let mut child = with_retries(|| {
+        Command::new(program)
+            .args(long_path_arguments())
+            .arg("-j")
+            .args(tags.iter().map(|tag| format!("-{tag}")))
+            .arg("-@")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+    })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        for path in paths {
+            stdin.write_all(&argument_bytes(path.as_os_str()))?;
+            stdin.write_all(b"\n")?;
+        }
+    }
+
+    // exiftool exits with an error if any file failed, but still reports on the rest, so ignore the status.
+    let output = child.wait_with_output()?;
+
+    // Output is empty if none of the files could be read
+    if output.stdout.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let Ok(Value::Array(files)) = serde_json::from_slice::<Value>(&output.stdout) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "exiftool didn't output a valid JSON array",
+        ));
+    };
+    let mut map = HashMap::new();
+
+    for file in files {
+        let Value::Object(file) = file else {
+            continue;
+        };
+        let Some(Value::String(path)) = file.get("SourceFile") else {
+            continue;
+        };
+        let values = tags
+            .iter()
+            .filter_map(|tag| match file.get(*tag) {
+                Some(Value::String(value)) => Some((tag.to_string(), value.clone())),
+                // Values that look like numbers are output as such, e.g. a "DateCreated" of just "2023"
+                Some(Value::Number(value)) => Some((tag.to_string(), value.to_string())),
+                _ => None,
+            })
+            .collect();
+
+        map.insert(PathBuf::from(path), values);
+    }
+
+    Ok(map)
+}
+
+// Reads a single tag of a single file, which is what every tag that isn't batched goes through.
+// It's a trait so that tests can stand in for exiftool with canned output.
+pub trait TagReader {
+    // Returns the raw output of "exiftool -<tag> -s3 <path>", which is empty if the tag doesn't exist
+    fn read_tag(&self, tag: &str, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+// Long-lived "exiftool -stay_open True -@ -" processes that are fed one set of arguments at a time,
+// which saves the (~200ms) Perl startup for every file that isn't part of the batched read.
+// Threads reading at the same time each get their own process, so "--jobs" doesn't wait on a single one.
+// The processes are only started once they're needed, falling back to one process per call if they can't be.
+pub struct ExiftoolSession {
+    program: PathBuf,
+    // The processes that aren't reading anything right now
+    idle: Mutex<Vec<StayOpen>>,
+    // Set once a process couldn't be started or failed midway, every tag gets its own process from then on
+    unavailable: AtomicBool,
+}
+
+// A running "-stay_open" process, which is asked to exit when dropped
+struct StayOpen {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExiftoolSession {
+    pub fn new(program: &Path) -> Self {
+        Self {
+            program: program.to_path_buf(),
+            idle: Mutex::new(Vec::new()),
+            unavailable: AtomicBool::new(false),
+        }
+    }
+
+    // Runs a separate exiftool process just for these arguments
+    fn read_tag_once(&self, arguments: &[OsString]) -> io::Result<Vec<u8>> {
+        Ok(with_retries(|| {
+            Command::new(&self.program)
+                .args(long_path_arguments())
+                .args(arguments)
+                .output()
+        })?
+        .stdout)
+    }
+
+    // Takes a process nobody is using, or starts another one if they're all busy
+    fn take_process(&self) -> io::Result<StayOpen> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .pop();
+
+        match idle {
+            Some(process) => Ok(process),
+            None => with_retries(|| StayOpen::start(&self.program)),
+        }
+    }
+}
+
+impl StayOpen {
+    fn start(program: &Path) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(long_path_arguments())
+            .arg("-stay_open")
+            .arg("True")
+            .arg("-@")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::other("exiftool pipes aren't available"));
+        };
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    // Sends one argument per line followed by "-execute", then reads everything until exiftool reports "{ready}"
+    fn execute(&mut self, arguments: &[OsString]) -> io::Result<Vec<u8>> {
+        for argument in arguments {
+            self.stdin.write_all(&argument_bytes(argument))?;
+            self.stdin.write_all(b"\n")?;
+        }
+
+        writeln!(self.stdin, "-execute")?;
+        self.stdin.flush()?;
+
+        let mut output = Vec::new();
+
+        loop {
+            let mut line = Vec::new();
+
+            if self.stdout.read_until(b'\n', &mut line)? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+
+            if line.trim_ascii_end() == b"{ready}" {
+                return Ok(output);
+            }
+
+            output.append(&mut line);
+        }
+    }
+}
+
+impl TagReader for ExiftoolSession {
+    fn read_tag(&self, tag: &str, path: &Path) -> io::Result<Vec<u8>> {
+        let arguments = [
+            OsString::from(format!("-{tag}")),
+            OsString::from("-s3"),
+            path.as_os_str().to_os_string(),
+        ];
+
+        if self.unavailable.load(Ordering::Relaxed) {
+            return self.read_tag_once(&arguments);
+        }
+
+        let Ok(mut process) = self.take_process() else {
+            self.unavailable.store(true, Ordering::Relaxed);
+            return self.read_tag_once(&arguments);
+        };
+
+        match process.execute(&arguments) {
+            Ok(output) => {
+                self.idle
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner())
+                    .push(process);
+                Ok(output)
+            }
+            // The process is in an unknown state if it failed midway, so don't reuse it.
+            // Try this file again on its own though, it might just have been bad luck.
+            Err(_) => {
+                let _ = process.child.kill();
+                self.unavailable.store(true, Ordering::Relaxed);
+                self.read_tag_once(&arguments)
+            }
+        }
+    }
+}
+
+impl Drop for StayOpen {
+    fn drop(&mut self) {
+        // Ask the process to exit on its own, then wait so it isn't left behind
+        let _ = writeln!(self.stdin, "-stay_open\nFalse");
+        let _ = self.stdin.flush();
+        let _ = self.child.wait();
+    }
+}
+
+// The bytes of an argument as exiftool reads them from an argument file.
+// On Unix that's the path exactly as the filesystem has it, even if it isn't valid UTF-8.
+#[cfg(unix)]
+fn argument_bytes(argument: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(argument.as_bytes())
+}
+
+// Elsewhere exiftool expects UTF-8, which Windows paths can always be converted to unless they contain unpaired surrogates.
+#[cfg(not(unix))]
+fn argument_bytes(argument: &OsStr) -> Cow<'_, [u8]> {
+    match argument.to_string_lossy() {
+        Cow::Borrowed(argument) => Cow::Borrowed(argument.as_bytes()),
+        Cow::Owned(argument) => Cow::Owned(argument.into_bytes()),
+    }
+}
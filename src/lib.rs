@@ -0,0 +1,59 @@
+// The core of ios2exif: extracting timestamps from media files and planning/applying the renames.
+// The binary (main.rs) is just a command line wrapper around this.
+
+mod cache;
+mod config;
+mod datetime;
+mod error;
+mod exiftool;
+mod lock;
+mod logging;
+mod manifest;
+mod metadata;
+mod options;
+mod plan;
+mod png;
+mod quicktime;
+mod sniff;
+mod state;
+mod watch;
+
+pub use cache::{cache_path, CachedTagReader, ExiftoolCache};
+pub use config::{config_path, load_config, Config};
+pub use datetime::{
+    convert_timezone, format_datetime, parse_date_bound, parse_datetime,
+    parse_datetime_with_offset, parse_iso_datetime, parse_offset, parse_subsec, DateTimeParts,
+    Timezone,
+};
+pub use error::TimestampError;
+pub use exiftool::{
+    is_exiftool_available, read_tags_batch, validate_exiftool_path, ExiftoolSession, ExiftoolTags,
+    TagReader, BATCH_TAGS,
+};
+pub use lock::{is_lock_file, lock_directories, DirectoryLock, LOCK_FILE_NAME};
+pub use logging::{
+    is_enabled, log_format, log_message, set_log_format, set_verbosity, Level, LogFormat, Verbosity,
+};
+pub use manifest::{load_manifest, Manifest};
+pub use metadata::{
+    get_all_timestamps, get_camera, get_gps_coordinates, get_timestamp_and_rename_pair,
+    get_timestamp_from_epoch, get_timestamp_from_exif, get_timestamp_from_exif_digitized,
+    get_timestamp_from_exif_gps, get_timestamp_from_exiftool, get_timestamp_from_mtime,
+    get_timestamp_from_png, get_timestamp_from_quicktime_native, get_timestamp_from_takeout,
+    DateTag, MediaKind,
+};
+pub use options::{
+    format_dup_suffix, format_with_separators, parse_extension_list, sanitize_filename_part,
+    strip_dup_suffix, validate_dup_format, validate_filename_part, validate_format,
+    validate_template, CollisionPolicy, DateFolders, ExtensionCase, Options, SortOrder,
+    DEFAULT_DUP_FORMAT, DEFAULT_FORMAT, TEMPLATE_TOKENS,
+};
+pub use plan::{
+    apply_plan, collect_files, is_found_in_folder, plan_renames, prune_empty_directories,
+    undo_renames, FileInfo, RenamePlan, Status, Summary,
+};
+pub use png::read_png_date;
+pub use quicktime::read_creation_time;
+pub use sniff::sniff_extension;
+pub use state::{state_path, LastRuns};
+pub use watch::{watch_directories, watched_path, Settling, SETTLE_TIME};
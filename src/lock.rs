@@ -0,0 +1,83 @@
+use crate::verbose;
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+// Created in every folder being renamed in, so that a second run in the same folder refuses to start
+pub const LOCK_FILE_NAME: &str = ".ios2exif.lock";
+
+// The lock files created by "lock_directories", which are removed again once this is dropped
+#[derive(Debug, Default)]
+pub struct DirectoryLock {
+    files: Vec<PathBuf>,
+}
+
+impl Drop for DirectoryLock {
+    fn drop(&mut self) {
+        for file in &self.files {
+            // Nothing can be done about it this late, and the next run will say what to do
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+// Whether the file is one of the lock files, which are never renamed
+pub fn is_lock_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == LOCK_FILE_NAME)
+}
+
+// Creates a lock file in each of the given folders (or the folder of each given file)
+// Fails if one of them already exists, meaning another run is still going (or was killed), unless "force" is set.
+// Folders where the lock file can't be created aren't locked, e.g. read-only ones that "--copy" only reads from.
+pub fn lock_directories(paths: &[PathBuf], force: bool) -> Result<DirectoryLock, String> {
+    let directories: BTreeSet<PathBuf> = paths
+        .iter()
+        .filter_map(|path| {
+            let directory = if path.is_dir() {
+                path.as_path()
+            } else {
+                path.parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."))
+            };
+
+            fs::canonicalize(directory).ok()
+        })
+        .collect();
+    let mut lock = DirectoryLock::default();
+
+    for directory in directories {
+        let file = directory.join(LOCK_FILE_NAME);
+
+        match create_lock_file(&file, force) {
+            Ok(()) => lock.files.push(file),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(format!(
+                    "Error: Another ios2exif is already running in \"{}\". If that's not the case (e.g. it was killed), remove \"{}\" or pass \"--force\".",
+                    directory.display(),
+                    file.display()
+                ));
+            }
+            Err(error) => verbose!("Not locking \"{}\" - {error}", directory.display()),
+        }
+    }
+
+    Ok(lock)
+}
+
+// The lock file holds the ID of the process, for anyone wondering which one is running
+// Nothing reads it back, the folder is locked for as long as the file exists (even if that process is gone).
+fn create_lock_file(path: &Path, force: bool) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(force)
+        .create_new(!force)
+        .open(path)?;
+
+    writeln!(file, "{}", process::id())
+}
@@ -0,0 +1,132 @@
+use serde_json::json;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+// How much is printed, warnings and errors are always printed regardless
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Verbosity {
+    // Only warnings and errors
+    Quiet,
+    // Also the outcome of every file
+    Normal,
+    // Also where every timestamp came from
+    Verbose,
+}
+
+// Set once at startup, a global so that every module respects it without passing it around
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+// Whether messages of this level should be printed
+pub fn is_enabled(verbosity: Verbosity) -> bool {
+    verbosity as u8 <= VERBOSITY.load(Ordering::Relaxed)
+}
+
+// Prints a line to stdout unless "--quiet" was passed
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::is_enabled($crate::Verbosity::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Prints a line to stdout only if "--verbose" was passed
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::is_enabled($crate::Verbosity::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+// How warnings and errors are printed to stderr, set with "--log-format"
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    // "Warning: ..." and "Error: ..." lines for people
+    Text,
+    // A JSON object per line for scripts, e.g. to find out which files failed in CI
+    Json,
+}
+
+// A global for the same reason as the verbosity
+static LOG_FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+pub fn set_log_format(format: LogFormat) {
+    LOG_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn log_format() -> LogFormat {
+    if LOG_FORMAT.load(Ordering::Relaxed) == LogFormat::Json as u8 {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+// Prints a warning or error to stderr, either as "Warning: <message>" or as a JSON line like
+// {"level":"warning","file":"IMG_0001.HEIC","code":"no_datetimeoriginal","message":"..."}
+// The code says what went wrong without having to parse the message, "file" is null if it isn't about one.
+// Codes starting with "exiftool_" are about exiftool itself, which the text is prefixed with "[exiftool]" for.
+pub fn log_message(level: Level, code: &str, file: Option<&Path>, message: &str) {
+    match log_format() {
+        LogFormat::Text => {
+            let tool = if code.starts_with("exiftool_") {
+                "[exiftool] "
+            } else {
+                ""
+            };
+            let level = match level {
+                Level::Warning => "Warning",
+                Level::Error => "Error",
+            };
+
+            eprintln!("{tool}{level}: {message}");
+        }
+        LogFormat::Json => {
+            let level = match level {
+                Level::Warning => "warning",
+                Level::Error => "error",
+            };
+
+            eprintln!(
+                "{}",
+                json!({
+                    "level": level,
+                    "file": file.map(|file| file.to_string_lossy()),
+                    "code": code,
+                    "message": message,
+                })
+            );
+        }
+    }
+}
+
+// Prints a warning to stderr, e.g. warning!("path_too_long", Some(&path), "The new path of ...")
+#[macro_export]
+macro_rules! warning {
+    ($code:expr, $file:expr, $($arg:tt)*) => {
+        $crate::log_message($crate::Level::Warning, $code, $file, &format!($($arg)*))
+    };
+}
+
+// Prints an error to stderr, the same way as warning!()
+#[macro_export]
+macro_rules! error {
+    ($code:expr, $file:expr, $($arg:tt)*) => {
+        $crate::log_message($crate::Level::Error, $code, $file, &format!($($arg)*))
+    };
+}
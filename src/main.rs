@@ -1,390 +1,1075 @@
+mod cli;
+
+use clap::ArgMatches;
+use clap_complete::Shell;
+use glob::Pattern;
+use ios2exif::{
+    apply_plan, cache_path, config_path, error, format_with_separators, get_all_timestamps, info,
+    is_exiftool_available, load_config, load_manifest, lock_directories, log_format,
+    parse_date_bound, parse_extension_list, plan_renames, prune_empty_directories, set_log_format,
+    set_verbosity, state_path, undo_renames, validate_dup_format, validate_exiftool_path,
+    validate_filename_part, validate_format, validate_template, warning, watch_directories,
+    CollisionPolicy, Config, DateFolders, DateTag, ExiftoolCache, ExiftoolSession, ExtensionCase,
+    FileInfo, LastRuns, LogFormat, Options, RenamePlan, SortOrder, Status, Summary, Timezone,
+    Verbosity,
+};
 use std::{
-    collections::BTreeMap,
-    env,
-    fs::{self, File},
-    io::BufReader,
+    collections::HashSet,
+    env, fs,
+    io::{self, IsTerminal, Write},
     path::PathBuf,
-    process::Command,
-    str,
+    process::ExitCode,
+    time::SystemTime,
 };
 
-// General Rules of Thumb:
-// EXIF DateTimeOriginal = Photos taken via camera and modern screenshots (after 2020)
-// QuickTime CreationDate = Videos (MOV) taken via camera
-// QuickTime CreateDate = Screen Recordings (MOV/MP4)
-// XMP DateCreated = Older screenshots (before 2020)
-
-// "CreationDate" is actually QuickTime metadata, not EXIF metadata. (https://superuser.com/a/1285932)
-// "-s3" is to just get the value without the header. (https://photo.stackexchange.com/a/56678)
-// Example: "exiftool -DateTimeOriginal -s3 2023-05-25_19-47-30.heic" gives value "2023:05:25 19:47:30"
-// Example: "exiftool -CreationDate -s3 2023-05-14_21-34-06.mov" gives value "2023:05:14 21:34:06-05:00"
-// Example: "exiftool -CreateDate -s3 2023-05-14_21-34-06.mp4" gives value "2023:05:14 21:34:06" (timezone-unaware, manual checking required)
-// Example: "exiftool -DateCreated -s3 2019-10-15_02-08-00.png" gives value "2019:10:15 02:08:48"
-
-struct FileInfo {
-    path: String,
-    new_name: String,
+// The exit code of a run, so that scripts can tell what went wrong
+#[derive(Clone, Copy)]
+enum Outcome {
+    Success = 0,
+    // Conflicting timestamps or destinations were found, so nothing was renamed (except for the rest with "--keep-going")
+    Conflicts = 1,
+    // Some files couldn't be renamed (or copied)
+    Failed = 2,
+    // Files that need exiftool were skipped since it isn't available
+    ExiftoolMissing = 3,
+    // The arguments or paths can't be used, so nothing was done
+    InvalidUsage = 4,
+    // The warnings weren't accepted (or couldn't be, without a terminal)
+    Aborted = 5,
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome as u8)
+    }
+}
+
+// Failed renames are worse than conflicts (left behind by "--keep-going") and skipped videos,
+// which are worse than nothing going wrong
+fn outcome(plan: &RenamePlan, summary: &Summary) -> Outcome {
+    if summary.failed > 0 {
+        Outcome::Failed
+    } else if plan.has_conflicts {
+        Outcome::Conflicts
+    } else if plan.exiftool_missing {
+        Outcome::ExiftoolMissing
+    } else {
+        Outcome::Success
+    }
 }
 
-// Maybe implement command line arguments that target specific files.
-fn main() {
-    println!(
+fn main() -> ExitCode {
+    // Anything modified while running is looked at again by the next run with "--since-last-run"
+    let started = SystemTime::now();
+
+    let matches = match cli::command().try_get_matches() {
+        Ok(matches) => matches,
+        Err(error) => {
+            let _ = error.print();
+            // "--help" and "--version" are "errors" too, just printed to stdout
+            return if error.use_stderr() {
+                Outcome::InvalidUsage
+            } else {
+                Outcome::Success
+            }
+            .into();
+        }
+    };
+
+    // Nothing to do with renaming (or the config file)
+    if let Some(&shell) = matches.get_one::<Shell>("generate-completions") {
+        clap_complete::generate(
+            shell,
+            &mut cli::command(),
+            env!("CARGO_PKG_NAME"),
+            &mut io::stdout(),
+        );
+        return Outcome::Success.into();
+    }
+
+    let (mode, mut options, paths) = match parse_arguments(&matches) {
+        Ok(arguments) => arguments,
+        Err(error_message) => {
+            print_error("invalid_usage", &error_message);
+            return Outcome::InvalidUsage.into();
+        }
+    };
+
+    // The JSON report has to be the only thing on stdout
+    set_verbosity(if options.json {
+        Verbosity::Quiet
+    } else {
+        options.verbosity
+    });
+    info!(
         "-=[ {} - v{} ]=-",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION")
     );
 
-    // Get file iterator of current directory
-    let Ok(current_directory) = env::current_dir() else {
-        return eprintln!(
-            "The current working directory either doesn't exist or isn't accessible."
-        );
-    };
-    let Ok(files) = fs::read_dir(current_directory) else {
-        return eprintln!("The current working directory isn't a valid directory.");
-    };
-    let mut needs_confirmation = false;
-    let mut must_exit = false;
-    let mut map = BTreeMap::<String, FileInfo>::new(); // Map<timestamp, path>, used to test for duplicate timestamps.
-
-    for file in files {
-        // Ignore the file if it can't be read
-        let Ok(file) = file else {
-            eprintln!("Warning: A file can't be read.");
-            needs_confirmation = true;
-            continue;
-        };
-        let path = file.path();
-        let path_str = path.to_string_lossy().to_string();
-        // Get lowercase extension (if any)
-        let extension = {
-            let extension = path.extension();
-
-            match extension {
-                Some(extension) => Some(extension.to_string_lossy().to_lowercase()),
-                None => None,
-            }
+    // Fall back to the current working directory if no paths were given
+    let paths = if paths.is_empty() {
+        let Ok(current_directory) = env::current_dir() else {
+            error!(
+                "invalid_usage",
+                None, "The current working directory either doesn't exist or isn't accessible."
+            );
+            return Outcome::InvalidUsage.into();
         };
+        vec![current_directory]
+    } else {
+        paths
+    };
 
-        // Ignore directories
-        if path.is_dir() {
-            continue;
+    match mode {
+        Mode::Inspect => return print_metadata(&paths, &options).into(),
+        Mode::Undo => return undo(&paths, &options).into(),
+        Mode::Rename => {}
+    }
+
+    let outcome = run(&paths, &paths, &options, &mut HashSet::new());
+    let renamed = matches!(outcome, Outcome::Success | Outcome::ExiftoolMissing);
+
+    // Otherwise the files that failed wouldn't be tried again, as they weren't modified since
+    if renamed && !options.dry_run && !options.simulate_conflicts {
+        save_last_runs(&mut options, &paths, started);
+    }
+
+    if !options.watch || matches!(outcome, Outcome::InvalidUsage) {
+        return outcome.into();
+    }
+
+    // The new files are all modified after the last run anyway
+    options.last_runs = None;
+    // The new names of the files renamed while watching, which show up as new files too
+    let mut produced = HashSet::new();
+    info!("Watching for new files, press Ctrl+C to stop...");
+
+    let result = watch_directories(&paths, &options, |files| {
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|file| !fs::canonicalize(file).is_ok_and(|file| produced.remove(&file)))
+            .collect();
+
+        if !files.is_empty() {
+            run(&paths, &files, &options, &mut produced);
         }
+    });
 
-        // Determine what to do based on the file extension
-        let try_exif_first = match extension {
-            Some(ref extension) => match extension.as_str() {
-                // Photos
-                "jpg" => true,
-                "jpeg" => true,
-                "png" => true,
-                "heic" => true,
-                // Videos
-                "mov" => false,
-                "mp4" => false,
-                _ => {
-                    println!("Warning: Unsupported extension \".{extension}\", ignoring...");
-                    continue;
-                }
-            },
-            _ => true,
-        };
+    match result {
+        Ok(()) => Outcome::Success.into(),
+        Err(error_message) => {
+            print_error("watch_failed", &error_message);
+            Outcome::Failed.into()
+        }
+    }
+}
 
-        let result = get_timestamp_and_rename_pair(&path, &path_str, extension, try_exif_first);
-        let Some(result) = result else {
-            needs_confirmation = true;
-            continue;
-        };
-        let (timestamp, new_name) = result;
-
-        // Error if two files have the same timestamp, as that will definitely cause problems.
-        // Continue the loop to show all occurrences.
-        if map.contains_key(&timestamp) {
-            eprintln!(
-                    "Error: Attempted to add \"{path_str}\"\n\t...but the timestamp ({timestamp}) already exists in file: \"{}\"",
-                    map[&timestamp].path
-                );
-            must_exit = true;
-        } else {
-            map.insert(
-                timestamp,
-                FileInfo {
-                    path: path_str,
-                    new_name,
-                },
-            );
+// Renames the files found in "paths" (which are files with "--watch"), locking the folders given in "roots" meanwhile
+// "produced" gets the new paths of the files renamed (or copied), which "--watch" doesn't have to look at.
+fn run(
+    roots: &[PathBuf],
+    paths: &[PathBuf],
+    options: &Options,
+    produced: &mut HashSet<PathBuf>,
+) -> Outcome {
+    // Held until the end, nothing is renamed in a dry run so another run doesn't matter then
+    let _lock = if options.dry_run || options.simulate_conflicts {
+        None
+    } else {
+        match lock_directories(roots, options.force) {
+            Ok(lock) => Some(lock),
+            Err(error_message) => {
+                print_error("locked", &error_message);
+                return Outcome::InvalidUsage;
+            }
         }
+    };
+
+    let mut plan = match plan_renames(paths, options) {
+        Ok(plan) => plan,
+        Err(error_message) => {
+            print_error("invalid_usage", &error_message);
+            return Outcome::InvalidUsage;
+        }
+    };
+
+    // Right away, in case the renames aren't confirmed
+    save_cache(&plan, options);
+
+    // Otherwise there's little more than "Processed 0 file(s)" to tell that it ran at all (with "--json", the array is empty)
+    if plan.summary.processed == plan.summary.unsupported && plan.summary.unchanged == 0 {
+        let paths: Vec<String> = paths
+            .iter()
+            .map(|path| format!("\"{}\"", path.display()))
+            .collect();
+        info!("No supported media files found in {}.", paths.join(", "));
     }
 
-    if must_exit {
-        eprintln!("Error: Found conflicting timestamps, exiting...");
-        return;
+    // Show the full picture so every conflict can be fixed in one go, without renaming anything
+    if options.simulate_conflicts {
+        report_conflicts(&plan);
+
+        if !options.report_only_conflicts {
+            report(&plan, &plan.summary, options, "");
+        }
+
+        return if plan.has_conflicts {
+            Outcome::Conflicts
+        } else {
+            Outcome::Success
+        };
     }
 
-    if needs_confirmation {
-        use text_io::read;
+    if plan.has_conflicts && options.keep_going {
+        plan.summary.conflicts += plan.skip_conflicts();
+    } else if plan.has_conflicts {
+        report(&plan, &plan.summary, options, "");
+        error!(
+            "conflicts",
+            None, "Found conflicting timestamps or destinations, exiting..."
+        );
+        return Outcome::Conflicts;
+    }
 
-        // Ask Y/y/N/n, exit otherwise.
-        print!("Are all the warnings are okay with you? [y/n] ");
-        let response: String = read!();
+    // Nothing is touched in a dry run, so there's nothing to confirm either.
+    if options.dry_run {
+        let action = if options.copy { "copy" } else { "rename" };
+        // Count what would happen as if it did, the "[dry-run]" prefix makes the difference clear
+        let mut summary = plan.summary.clone();
 
-        match response.as_str() {
-            "Y" => {}
-            "y" => {}
-            "N" => {
-                return println!("Exiting...");
+        for info in plan.files().into_iter().flat_map(FileInfo::with_companions) {
+            if !info.is_pending() {
+                continue;
             }
-            "n" => {
-                return println!("Exiting...");
+
+            if options.copy {
+                summary.copied += 1;
+            } else {
+                summary.renamed += 1;
             }
-            _ => {
-                return eprintln!("Invalid response, exiting...");
+
+            info!(
+                "[dry-run] Would {action} \"{}\" to \"{}\" (timestamp \"{}\").",
+                info.path.display(),
+                info.new_path.display(),
+                info.timestamp
+            );
+        }
+
+        report(&plan, &summary, options, "[dry-run] ");
+
+        if plan.has_conflicts {
+            report_conflicts(&plan);
+        }
+
+        return outcome(&plan, &summary);
+    }
+
+    if plan.needs_confirmation && options.yes {
+        info!("Warnings were found, but continuing anyway since \"--yes\" was passed.");
+    } else if plan.needs_confirmation && !is_interactive(options) {
+        // Prompting without a terminal would just hang (or read garbage), so bail out instead.
+        error!("not_interactive", None, "Warnings were found and confirmation is required, but the program isn't running interactively. Pass \"--yes\" to accept them, exiting...");
+        return Outcome::Aborted;
+    } else if plan.needs_confirmation {
+        let choices = match options.default_answer {
+            Some(true) => "[Y/n]",
+            Some(false) => "[y/N]",
+            None => "[y/n]",
+        };
+
+        // Ask yes/no (in any case), and again if it's anything else.
+        // The end of the input is a no, and so are too many empty lines when there's no default answer.
+        let mut empty = 0;
+
+        loop {
+            let response = read_answer(&format!(
+                "Are all the warnings are okay with you? {choices}"
+            ));
+
+            match (response.as_deref(), options.default_answer) {
+                (Some("y" | "yes"), _) | (Some(""), Some(true)) => break,
+                (Some("n" | "no"), _) | (Some(""), Some(false)) | (None, _) => {
+                    println!("Exiting...");
+                    return Outcome::Aborted;
+                }
+                (Some(""), None) => {
+                    empty += 1;
+
+                    if empty >= MAX_EMPTY_ANSWERS {
+                        eprintln!("No response was given, exiting...");
+                        return Outcome::Aborted;
+                    }
+
+                    eprintln!("Invalid response, please answer y (yes) or n (no).");
+                }
+                _ => eprintln!("Invalid response, please answer y (yes) or n (no)."),
             }
         }
     }
 
-    // Once confirmed or no warnings, then proceed with the renaming.
-    for (timestamp, info) in map {
-        let result = fs::rename(&info.path, &info.new_name);
+    if options.interactive {
+        if !is_interactive(options) {
+            error!("not_interactive", None, "\"--interactive\" needs to ask about every file, but the program isn't running interactively, exiting...");
+            return Outcome::Aborted;
+        }
 
-        if let Err(error) = result {
-            eprintln!("Error: Renaming failed for \"{}\" - {error}", info.path);
-        } else {
-            println!(
-                "Renaming success for \"{}\" to timestamp \"{timestamp}\".",
-                info.path
+        confirm_each(&mut plan, options);
+    }
+
+    // Once confirmed or no warnings, then proceed with the renaming (or copying).
+    match apply_plan(&mut plan, options) {
+        Ok(mut summary) => {
+            // Only once every file was moved, as one that failed would still be in its folder
+            if options.prune_empty && !options.copy && summary.failed == 0 {
+                prune_empty_directories(&plan, roots, &mut summary);
+            }
+
+            save_cache(&plan, options);
+
+            produced.extend(
+                plan.files()
+                    .into_iter()
+                    .flat_map(FileInfo::with_companions)
+                    .filter(|info| matches!(info.status, Status::Renamed | Status::Copied))
+                    .filter_map(|info| fs::canonicalize(&info.new_path).ok()),
             );
+
+            report(&plan, &summary, options, "");
+
+            // Only reached with "--keep-going", so the conflicts come after everything else
+            if plan.has_conflicts {
+                report_conflicts(&plan);
+            }
+
+            outcome(&plan, &summary)
+        }
+        Err(error_message) => {
+            print_error("apply_failed", &error_message);
+            Outcome::Failed
         }
     }
 }
 
-// Try exif first, otherwise use "exiftool" to read miscellaneous metadata (QuickTime, etc.)
-// Returns a pair of (timestamp, new_name) if successful
-fn get_timestamp_and_rename_pair(
-    path: &PathBuf,
-    path_str: &String,
-    extension: Option<String>,
-    try_exif_first: bool,
-) -> Option<(String, String)> {
-    if try_exif_first {
-        let timestamp = get_timestamp_from_exif(path, path_str);
-
-        match timestamp {
-            Ok(timestamp) => {
-                if let Some(extension) = extension {
-                    return Some((timestamp.clone(), format!("{timestamp}.{extension}")));
-                } else {
-                    return Some((timestamp.clone(), timestamp));
+// Prints every date found in each file with "ios2exif inspect", e.g.
+// "/photos/IMG_0001.HEIC":
+//     EXIF DateTimeOriginal: 2023-05-25 19:47:30 -04:00 ("2023:05:25 19:47:30")
+//     EXIF GPS time: none - The metadata doesn't include GPSDateStamp for file "/photos/IMG_0001.HEIC"
+fn print_metadata(paths: &[PathBuf], options: &Options) -> Outcome {
+    if let Some(directory) = paths.iter().find(|path| !path.is_file()) {
+        error!(
+            "invalid_usage",
+            None,
+            "\"inspect\" only takes files, but \"{}\" isn't one.",
+            directory.display()
+        );
+        return Outcome::InvalidUsage;
+    }
+
+    let exiftool_available = !options.no_exiftool && is_exiftool_available(&options.exiftool_path);
+    let session = ExiftoolSession::new(&options.exiftool_path);
+
+    if !exiftool_available {
+        info!("Note: exiftool isn't available, so only what can be read without it is shown.");
+    }
+
+    for path in paths {
+        let path_str = path.display().to_string();
+        println!("\"{path_str}\":");
+
+        for (source, result) in
+            get_all_timestamps(path, &path_str, options, &session, exiftool_available)
+        {
+            match result {
+                Ok(parts) => {
+                    let mut datetime = parts.datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+
+                    if let Some(subsec) = &parts.subsec {
+                        datetime += &format!(".{subsec}");
+                    }
+
+                    if let Some(offset) = parts.offset {
+                        datetime += &format!(" {offset}");
+                    }
+
+                    println!("    {source}: {datetime} (\"{}\")", parts.raw);
                 }
-            }
-            Err(error_message) => {
-                eprintln!("{}", error_message);
+                Err(error) => println!("    {source}: none - {error}"),
             }
         }
     }
 
-    // Try CreationDate
-    let timestamp = get_timestamp_from_exiftool_creationdate(path_str);
+    Outcome::Success
+}
 
-    if let Some(timestamp) = timestamp {
-        if let Some(extension) = extension {
-            return Some((timestamp.clone(), format!("{timestamp}.{extension}")));
-        } else {
-            return Some((timestamp.clone(), timestamp));
+// Renames the files back with "ios2exif undo", after the sidecar files written by "--sidecar"
+fn undo(paths: &[PathBuf], options: &Options) -> Outcome {
+    let _lock = if options.dry_run {
+        None
+    } else {
+        match lock_directories(paths, options.force) {
+            Ok(lock) => Some(lock),
+            Err(error_message) => {
+                print_error("locked", &error_message);
+                return Outcome::InvalidUsage;
+            }
         }
     };
 
-    // Try CreateDate with warning in filename
-    let timestamp = get_timestamp_from_exiftool_createdate(path_str);
-
-    if let Some(timestamp) = timestamp {
-        if let Some(extension) = extension {
-            return Some((timestamp.clone(), format!("{timestamp} (utc).{extension}",)));
-        } else {
-            return Some((timestamp.clone(), format!("{timestamp} (utc)")));
+    let summary = match undo_renames(paths, options) {
+        Ok(summary) => summary,
+        Err(error_message) => {
+            print_error("invalid_usage", &error_message);
+            return Outcome::InvalidUsage;
         }
     };
+    let prefix = if options.dry_run { "[dry-run] " } else { "" };
+    info!("{prefix}{summary}");
 
-    // Try DateCreated
-    let timestamp = get_timestamp_from_exiftool_datecreated(path_str);
+    if summary.failed > 0 {
+        Outcome::Failed
+    } else {
+        Outcome::Success
+    }
+}
 
-    if let Some(timestamp) = timestamp {
-        if let Some(extension) = extension {
-            return Some((timestamp.clone(), format!("{timestamp}.{extension}")));
-        } else {
-            return Some((timestamp.clone(), timestamp));
-        }
+// Records the run for "--since-last-run", as of when it started
+fn save_last_runs(options: &mut Options, paths: &[PathBuf], started: SystemTime) {
+    let Some(ref mut last_runs) = options.last_runs else {
+        return;
     };
 
-    // Nothing found otherwise
-    None
+    last_runs.record(paths, started);
+
+    if let Err(error) = last_runs.save() {
+        warning!(
+            "state_failed",
+            None,
+            "Failed to save when this run happened for \"--since-last-run\" - {error}"
+        );
+    }
 }
 
-// Returns a string of the formatted timestamp if successful
-// Ignore the entry if unsuccessful
-fn get_timestamp_from_exif(path: &PathBuf, path_str: &String) -> Result<String, String> {
-    let Ok(file) = File::open(path) else {
-        return Err(format!(
-            "Warning: Failed to open the file: \"{}\"",
-            path_str
-        ));
+// Saves what exiftool read for the next run with "--cache", under the new names of the files renamed so far
+fn save_cache(plan: &RenamePlan, options: &Options) {
+    let Some(ref cache) = options.cache else {
+        return;
     };
-    let mut bufreader = BufReader::new(&file);
-    let exifreader = exif::Reader::new();
 
-    // Ignore the file if invalid exif
-    let Ok(exif) = exifreader.read_from_container(&mut bufreader) else {
-        return Err(format!(
-            "Warning: The file doesn't contain valid EXIF: \"{}\"",
-            path_str
-        ));
-    };
+    for info in plan
+        .files()
+        .into_iter()
+        .flat_map(FileInfo::with_companions)
+        .filter(|info| info.status == Status::Renamed)
+    {
+        cache.rename(&info.path, &info.new_path);
+    }
 
-    // Extract the EXIF field DateTimeOriginal, use it as the new filename.
-    // Do not use DateTime, as that's supposed to update if the image is modified (https://gitlab.gnome.org/GNOME/gimp/-/issues/8160).
-    // Ignore if "ifd_num" isn't "primary", as that indicates that it's a thumbnail image, not a main image.
-    let Some(datetime) = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY) else {
-        return Err(format!("Warning: EXIF metadata is present but does not include DateTimeOriginal for file \"{path_str}\". Not renaming..."));
-    };
+    if let Err(error) = cache.save() {
+        warning!(
+            "cache_failed",
+            None,
+            "Failed to save the cache for \"--cache\" - {error}"
+        );
+    }
+}
 
-    // Convert timestamp of format "YYYY-MM-DD HH:MM:SS" to "YYYY-MM-DD_HH-MM-SS"
-    let mut timestamp = datetime.display_value().to_string();
-    // You don't actually need to use regex for this, just replace spaces and colons.
-    timestamp = timestamp.replace(" ", "_");
-    timestamp = timestamp.replace(":", "-");
+// Prints an error returned by the library, which already starts with "Error: "
+fn print_error(code: &str, message: &str) {
+    error!(
+        code,
+        None,
+        "{}",
+        message.strip_prefix("Error: ").unwrap_or(message)
+    );
+}
 
-    Ok(timestamp)
+// Whether the user can be prompted, which isn't possible without a terminal
+fn is_interactive(options: &Options) -> bool {
+    !options.no_interactive && io::stdin().is_terminal()
 }
 
-// If it fails for whatever reason, just ignore the entry
-fn get_timestamp_from_exiftool_creationdate(path_str: &String) -> Option<String> {
-    // Format: "YYYY:MM:DD HH:MM:SS-ZZ:00"
-    let output = Command::new("exiftool")
-        .arg("-CreationDate")
-        .arg("-s3")
-        .arg(path_str)
-        .output();
-
-    let Ok(output) = output else {
-        eprintln!(
-            "[exiftool] Warning: Failed to execute \"exiftool\" process on path \"{path_str}\"!"
-        );
-        return None;
-    };
+// How many empty lines the warnings prompt takes without a default answer before giving up
+const MAX_EMPTY_ANSWERS: usize = 3;
 
-    let length = output.stdout.len();
+// Prints the question and reads the whole line answering it (trimmed and lowercase)
+// Returns None once the input ended, e.g. with Ctrl-D.
+fn read_answer(question: &str) -> Option<String> {
+    print!("{question} ");
+    // The question doesn't end with a newline, so it wouldn't be shown before reading otherwise
+    io::stdout().flush().ok();
 
-    // Output is empty if metadata attribute doesn't exist
-    if length <= 0 {
-        eprintln!("[exiftool] Warning: No output for tag \"CreationDate\" on path \"{path_str}\"!");
-        return None;
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => {
+            // Move past the question, as the user didn't press enter
+            println!();
+            None
+        }
+        Ok(_) => Some(line.trim().to_lowercase()),
     }
+}
 
-    // -1 for ending newline
-    // -6 for timezone
-    let slice = &output.stdout[0..length - 7];
-    let timestamp = str::from_utf8(slice);
+// Asks whether to rename each file with "--interactive", marking the ones that shouldn't be as declined
+// Companions (e.g. the video of a Live Photo) go along with their photo.
+fn confirm_each(plan: &mut RenamePlan, options: &Options) {
+    let action = if options.copy { "Copy" } else { "Rename" };
+    // Set once "a" (all remaining) or "q" (quit) was answered, the rest are renamed or declined without asking
+    let mut remaining = None;
+    let mut declined = 0;
 
-    let Ok(timestamp) = timestamp else {
-        eprintln!(
-            "[exiftool] Warning: CreationDate \"{:?}\" should be a valid UTF-8 string on path \"{path_str}\"!",
-            slice
-        );
-        return None;
-    };
+    for info in plan.files_mut() {
+        if !info.is_pending() {
+            continue;
+        }
+
+        let accepted = match remaining {
+            Some(accepted) => accepted,
+            None => loop {
+                for info in info.with_companions() {
+                    println!(
+                        "\"{}\" -> \"{}\"",
+                        info.path.display(),
+                        info.new_path.display()
+                    );
+                }
+
+                // Ask y/n/a/q (or the whole words, in any case), and again if it's anything else.
+                // The end of the input quits.
+                let response = read_answer(&format!("{action}? [y/n/a/q]"));
 
-    let mut timestamp = timestamp.to_string();
-    // Convert timestamp of format "YYYY:MM:DD HH:MM:SS" to "YYYY-MM-DD_HH-MM-SS"
-    timestamp = timestamp.replace(" ", "_");
-    timestamp = timestamp.replace(":", "-");
+                match response.as_deref() {
+                    Some("y" | "yes") => break true,
+                    Some("n" | "no") => break false,
+                    Some("a" | "all") => {
+                        remaining = Some(true);
+                        break true;
+                    }
+                    Some("q" | "quit") | None => {
+                        remaining = Some(false);
+                        break false;
+                    }
+                    _ => eprintln!("Invalid response, please answer y (yes), n (no), a (all remaining) or q (quit)."),
+                }
+            },
+        };
 
-    Some(timestamp)
+        if !accepted {
+            declined += info.decline();
+        }
+    }
+
+    plan.summary.declined += declined;
 }
 
-// If it fails for whatever reason, just ignore the entry
-fn get_timestamp_from_exiftool_createdate(path_str: &String) -> Option<String> {
-    // Format: "YYYY:MM:DD HH:MM:SS"
-    let output = Command::new("exiftool")
-        .arg("-CreateDate")
-        .arg("-s3")
-        .arg(path_str)
-        .output();
-
-    let Ok(output) = output else {
-        eprintln!(
-            "[exiftool] Warning: Failed to execute \"exiftool\" process on path \"{path_str}\"!"
-        );
-        return None;
+// Prints the summary, along with the JSON report to stdout (moving the summary to stderr) if requested
+fn report(plan: &RenamePlan, summary: &Summary, options: &Options, prefix: &str) {
+    if options.json {
+        println!("{:#}", plan.to_json());
+        eprintln!("{prefix}{summary}");
+    } else {
+        println!("{prefix}{summary}");
+    }
+}
+
+// What the command line asked for, renaming being the default
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Rename,
+    // "ios2exif inspect", which only prints the dates found in the files
+    Inspect,
+    // "ios2exif undo", which renames the files back to the names their sidecar files recorded
+    Undo,
+}
+
+// Whether a flag was given, which is false for the flags the subcommand doesn't have
+fn flag(matches: &ArgMatches, name: &str) -> bool {
+    matches
+        .try_get_one::<bool>(name)
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(false)
+}
+
+// The value of an option (the last one if it was given multiple times), or None if it wasn't given
+fn value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches
+        .try_get_one::<String>(name)
+        .ok()
+        .flatten()
+        .map(String::as_str)
+}
+
+// Every value of an option that can be given multiple times, e.g. "--pattern"
+fn values<'a>(matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+    matches
+        .try_get_many::<String>(name)
+        .ok()
+        .flatten()
+        .map_or_else(Vec::new, |values| values.map(String::as_str).collect())
+}
+
+fn path(matches: &ArgMatches, name: &str) -> Option<PathBuf> {
+    matches.try_get_one::<PathBuf>(name).ok().flatten().cloned()
+}
+
+// Turns the command line arguments into options on top of the config file (if any)
+// Returns what to do along with the options and the files and/or directories to process
+// Command line options take precedence over the config file, which takes precedence over the built-in defaults.
+fn parse_arguments(matches: &ArgMatches) -> Result<(Mode, Options, Vec<PathBuf>), String> {
+    let (mode, matches) = match matches.subcommand() {
+        Some(("inspect", matches)) => (Mode::Inspect, matches),
+        Some(("undo", matches)) => (Mode::Undo, matches),
+        Some((_, matches)) if flag(matches, "print-metadata") => (Mode::Inspect, matches),
+        Some((_, matches)) => (Mode::Rename, matches),
+        None if flag(matches, "print-metadata") => (Mode::Inspect, matches),
+        None => (Mode::Rename, matches),
     };
 
-    let length = output.stdout.len();
+    // Right away, so that anything wrong with the rest of the arguments is printed that way too
+    if value(matches, "log-format") == Some("json") {
+        set_log_format(LogFormat::Json);
+    }
 
-    // Output is empty if metadata attribute doesn't exist
-    if length <= 0 {
-        eprintln!("[exiftool] Warning: No output for tag \"CreateDate\" on path \"{path_str}\"!");
-        return None;
+    let mut options = Options::default();
+    let paths = matches
+        .try_get_many::<PathBuf>("paths")
+        .ok()
+        .flatten()
+        .map_or_else(Vec::new, |paths| paths.cloned().collect());
+    let config = match config_path() {
+        Some(path) => load_config(&path)?,
+        None => Config::default(),
+    };
+
+    if let Some(ref timezone) = config.timezone {
+        options.timezone = parse_timezone(timezone)?;
+    }
+    if let Some(exiftool_path) = config.exiftool_path {
+        validate_exiftool_path(&exiftool_path)?;
+        options.exiftool_path = exiftool_path;
+    }
+    if let Some(dry_run) = config.dry_run {
+        options.dry_run = dry_run;
     }
 
-    // -1 for ending newline
-    let slice = &output.stdout[0..length - 1];
-    let timestamp = str::from_utf8(slice);
+    // The separators are only used to build the format, which is why they can't be combined with "--format"
+    let has_config_separators = config.date_separator.is_some()
+        || config.time_separator.is_some()
+        || config.datetime_separator.is_some();
+    let mut date_separator = config.date_separator.unwrap_or_else(|| String::from("-"));
+    let mut time_separator = config.time_separator.unwrap_or_else(|| String::from("-"));
+    let mut datetime_separator = config
+        .datetime_separator
+        .unwrap_or_else(|| String::from("_"));
 
-    let Ok(timestamp) = timestamp else {
-        eprintln!(
-            "[exiftool] Warning: CreateDate \"{:?}\" should be a valid UTF-8 string on path \"{path_str}\"!",
-            slice
-        );
-        return None;
-    };
+    for separator in [&date_separator, &time_separator, &datetime_separator] {
+        validate_filename_part(separator, "separator")?;
+    }
 
-    let mut timestamp = timestamp.to_string();
-    // Convert timestamp of format "YYYY:MM:DD HH:MM:SS" to "YYYY-MM-DD_HH-MM-SS"
-    timestamp = timestamp.replace(" ", "_");
-    timestamp = timestamp.replace(":", "-");
+    if let Some(format) = config.format {
+        if has_config_separators {
+            return Err(String::from(
+                "Error: The separators in the config file can't be combined with its \"format\", put the separators in the format instead.",
+            ));
+        }
 
-    Some(timestamp)
-}
+        validate_format(&format)?;
+        options.format = format;
+    } else if has_config_separators {
+        options.format =
+            format_with_separators(&date_separator, &time_separator, &datetime_separator);
+    }
 
-// If it fails for whatever reason, just ignore the entry
-fn get_timestamp_from_exiftool_datecreated(path_str: &String) -> Option<String> {
-    // Format: "YYYY:MM:DD HH:MM:SS"
-    let output = Command::new("exiftool")
-        .arg("-DateCreated")
-        .arg("-s3")
-        .arg(path_str)
-        .output();
-
-    let Ok(output) = output else {
-        eprintln!(
-            "[exiftool] Warning: Failed to execute \"exiftool\" process on path \"{path_str}\"!"
-        );
-        return None;
+    // Only one of each of these is set, as the last one given overrides the others
+    if flag(matches, "dry-run") {
+        options.dry_run = true;
+    } else if flag(matches, "no-dry-run") {
+        options.dry_run = false;
+    }
+
+    if flag(matches, "quiet") {
+        options.verbosity = Verbosity::Quiet;
+    } else if flag(matches, "verbose") {
+        options.verbosity = Verbosity::Verbose;
+    }
+
+    if flag(matches, "lowercase-ext") {
+        options.extension_case = ExtensionCase::Lower;
+    } else if flag(matches, "uppercase-ext") {
+        options.extension_case = ExtensionCase::Upper;
+    } else if flag(matches, "preserve-ext") {
+        options.extension_case = ExtensionCase::Preserve;
+    }
+
+    if flag(matches, "by-year") {
+        options.date_folders = Some(DateFolders::Year);
+    } else if flag(matches, "by-month") {
+        options.date_folders = Some(DateFolders::Month);
+    } else if flag(matches, "by-day") {
+        options.date_folders = Some(DateFolders::Day);
+    }
+
+    if flag(matches, "default-yes") {
+        options.default_answer = Some(true);
+    } else if flag(matches, "default-no") {
+        options.default_answer = Some(false);
+    }
+
+    if flag(matches, "dedupe") {
+        options.collision_policy = CollisionPolicy::Suffix;
+    }
+
+    // clap only lets the values it lists in "--help" through
+    if let Some(policy) = value(matches, "collision-policy") {
+        options.collision_policy = match policy {
+            "error" => CollisionPolicy::Error,
+            "skip" => CollisionPolicy::Skip,
+            "suffix" => CollisionPolicy::Suffix,
+            _ => CollisionPolicy::Overwrite,
+        };
+    }
+
+    if let Some(order) = value(matches, "sort") {
+        options.sort = match order {
+            "name" => SortOrder::Name,
+            "size" => SortOrder::Size,
+            "mtime" => SortOrder::Mtime,
+            _ => SortOrder::Timestamp,
+        };
+    }
+
+    options.recursive = flag(matches, "recursive");
+    options.watch = flag(matches, "watch");
+    options.dedupe_content = flag(matches, "dedupe-content");
+    options.simulate_conflicts = flag(matches, "simulate-conflicts");
+    options.report_only_conflicts = flag(matches, "report-only-conflicts");
+    options.keep_going = flag(matches, "keep-going");
+    options.yes = flag(matches, "yes");
+    options.no_interactive = flag(matches, "no-interactive");
+    options.interactive = flag(matches, "interactive");
+    options.copy = flag(matches, "copy");
+    options.mtime_fallback = flag(matches, "mtime-fallback");
+    options.takeout = flag(matches, "takeout");
+    options.json = flag(matches, "json");
+    options.keep_original = flag(matches, "keep-original");
+    options.utc_suffix = flag(matches, "utc-suffix");
+    options.no_exiftool = flag(matches, "no-exiftool");
+    options.flat = flag(matches, "flat");
+    options.prefer_gps = flag(matches, "prefer-gps");
+    options.prefer_video_date = flag(matches, "prefer-video-date");
+    options.append_gps = flag(matches, "append-gps");
+    options.include_hidden = flag(matches, "include-hidden");
+    options.follow_symlinks = flag(matches, "follow-symlinks");
+    options.force = flag(matches, "force");
+    options.transactional = flag(matches, "transactional");
+    options.prune_empty = flag(matches, "prune-empty");
+    options.sidecar = flag(matches, "sidecar");
+    options.template_strict = flag(matches, "template-strict");
+    options.output_dir = path(matches, "output-dir");
+
+    // Implies "--recursive", as there's nothing to limit otherwise
+    if let Some(raw) = value(matches, "max-depth") {
+        let Ok(depth) = raw.parse() else {
+            return Err(String::from(
+                "Error: \"--max-depth\" must be a number of folder levels, e.g. 1 to only go one folder deep.",
+            ));
+        };
+
+        options.recursive = true;
+        options.max_depth = Some(depth);
+    }
+
+    for flag in ["--min-year", "--max-year"] {
+        let Some(raw) = value(matches, &flag[2..]) else {
+            continue;
+        };
+        let Ok(year) = raw.parse() else {
+            return Err(format!(
+                "Error: \"{raw}\" isn't a valid year for \"{flag}\"."
+            ));
+        };
+
+        if flag == "--min-year" {
+            options.min_year = year;
+        } else {
+            options.max_year = year;
+        }
+    }
+
+    if flag(matches, "cache") {
+        let Some(path) = cache_path() else {
+            return Err(String::from(
+                "Error: \"--cache\" needs a cache folder, but neither HOME nor XDG_CACHE_HOME (or LOCALAPPDATA on Windows) is set.",
+            ));
+        };
+
+        options.cache = Some(ExiftoolCache::load(&path));
+    }
+
+    if flag(matches, "since-last-run") {
+        let Some(path) = state_path() else {
+            return Err(String::from(
+                "Error: \"--since-last-run\" needs a folder to keep its state in, but neither HOME nor XDG_STATE_HOME (or LOCALAPPDATA on Windows) is set.",
+            ));
+        };
+
+        options.last_runs = Some(LastRuns::load(&path)?);
+    }
+
+    if let Some(manifest) = path(matches, "manifest") {
+        options.manifest = Some(load_manifest(&manifest)?);
+    }
+
+    for raw in values(matches, "exiftool-tag") {
+        // Also accept it the way it's passed to exiftool, e.g. "-SonyDateTime"
+        let tag = raw.strip_prefix('-').unwrap_or(raw);
+
+        if tag.is_empty()
+            || !tag
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || character == '_')
+        {
+            return Err(format!(
+                "Error: \"{raw}\" isn't a valid exiftool tag name for \"--exiftool-tag\", e.g. \"SonyDateTime\"."
+            ));
+        }
+
+        options.exiftool_tags.push(tag.to_string());
+    }
+
+    if let Some(raw) = value(matches, "jobs") {
+        match raw.parse() {
+            Ok(jobs) if jobs > 0 => options.jobs = jobs,
+            _ => {
+                return Err(String::from(
+                    "Error: \"--jobs\" must be a positive whole number.",
+                ))
+            }
+        }
+    }
+
+    if let Some(exiftool_path) = path(matches, "exiftool-path") {
+        validate_exiftool_path(&exiftool_path)?;
+        options.exiftool_path = exiftool_path;
+    }
+
+    if let Some(timezone) = value(matches, "tz") {
+        options.timezone = parse_timezone(timezone)?;
+    }
+
+    for flag in ["--after", "--before"] {
+        let Some(raw) = value(matches, &flag[2..]) else {
+            continue;
+        };
+        let Some(bound) = parse_date_bound(raw) else {
+            return Err(format!(
+                "Error: \"{raw}\" isn't a valid date for \"{flag}\", expected \"YYYY-MM-DD\" or \"YYYY-MM-DD HH:MM:SS\"."
+            ));
+        };
+
+        if flag == "--after" {
+            options.after = Some(bound);
+        } else {
+            options.before = Some(bound);
+        }
+    }
+
+    if let Some(list) = value(matches, "only") {
+        let extensions = parse_extension_list(list);
+
+        if extensions.is_empty() {
+            return Err(String::from(
+                "Error: \"--only\" requires at least one extension.",
+            ));
+        }
+
+        options.only = Some(extensions);
+    }
+
+    for raw in values(matches, "pattern") {
+        let pattern = Pattern::new(raw).map_err(|error| {
+            format!("Error: \"{raw}\" isn't a valid glob for \"--pattern\" - {error}")
+        })?;
+        options.patterns.push(pattern);
+    }
+
+    for list in values(matches, "exclude") {
+        options.exclude.extend(parse_extension_list(list));
+    }
+
+    if let Some(list) = value(matches, "tag-priority") {
+        let mut tags = Vec::new();
+
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let Some(tag) = DateTag::parse(name) else {
+                let names: Vec<_> = DateTag::ALL.iter().map(|tag| tag.name()).collect();
+                return Err(format!(
+                    "Error: Unknown tag \"{name}\" in \"--tag-priority\", expected any of {}.",
+                    names.join(", ")
+                ));
+            };
+
+            tags.push(tag);
+        }
+
+        if tags.is_empty() {
+            return Err(String::from(
+                "Error: \"--tag-priority\" requires at least one tag.",
+            ));
+        }
+
+        options.tag_priority = Some(tags);
+    }
+
+    let has_format = if let Some(format) = value(matches, "format") {
+        validate_format(format)?;
+        options.format = format.to_string();
+        true
+    } else {
+        false
     };
 
-    let length = output.stdout.len();
+    if let Some(template) = value(matches, "template") {
+        validate_template(template)?;
+        options.template = Some(template.to_string());
+    }
 
-    // Output is empty if metadata attribute doesn't exist
-    if length <= 0 {
-        eprintln!("[exiftool] Warning: No output for tag \"DateCreated\" on path \"{path_str}\"!");
-        return None;
+    if let Some(format) = value(matches, "dup-format") {
+        validate_dup_format(format)?;
+        options.dup_format = format.to_string();
     }
 
-    // -1 for ending newline
-    let slice = &output.stdout[0..length - 1];
-    let timestamp = str::from_utf8(slice);
+    if let Some(prefix) = value(matches, "prefix") {
+        validate_filename_part(prefix, "prefix")?;
+        options.prefix = prefix.to_string();
+    }
+
+    if let Some(suffix) = value(matches, "suffix") {
+        validate_filename_part(suffix, "suffix")?;
+        options.suffix = suffix.to_string();
+    }
+
+    for suffix in values(matches, "strip-suffix") {
+        if suffix.is_empty() {
+            return Err("Error: \"--strip-suffix\" needs some text to remove.".to_string());
+        }
+
+        options.strip_suffixes.push(suffix.to_string());
+    }
 
-    let Ok(timestamp) = timestamp else {
-        eprintln!(
-            "[exiftool] Warning: DateCreated \"{:?}\" should be a valid UTF-8 string on path \"{path_str}\"!",
-            slice
+    let mut has_separators = false;
+
+    for (name, separator) in [
+        ("date-sep", &mut date_separator),
+        ("time-sep", &mut time_separator),
+        ("datetime-sep", &mut datetime_separator),
+    ] {
+        if let Some(value) = value(matches, name) {
+            validate_filename_part(value, "separator")?;
+            *separator = value.to_string();
+            has_separators = true;
+        }
+    }
+
+    if has_separators && has_format {
+        return Err(String::from(
+            "Error: The separator options can't be combined with \"--format\", put the separators in the format instead.",
+        ));
+    }
+
+    // Only the problems are printed, which is why it wins over "--verbose"
+    if options.report_only_conflicts {
+        options.simulate_conflicts = true;
+        options.verbosity = Verbosity::Quiet;
+    }
+
+    // Overwritten files are gone for good, so that has to be asked for twice
+    if options.collision_policy == CollisionPolicy::Overwrite && !options.force {
+        return Err(String::from(
+            "Error: \"--collision-policy overwrite\" replaces existing files for good, pass \"--force\" as well if that's really what you want.",
+        ));
+    }
+
+    if let (Some(after), Some(before)) = (options.after, options.before) {
+        if after >= before {
+            return Err(String::from(
+                "Error: \"--after\" has to be earlier than \"--before\", otherwise no file would be renamed.",
+            ));
+        }
+    }
+
+    if options.min_year > options.max_year {
+        return Err(format!(
+            "Error: \"--min-year\" ({}) can't be later than \"--max-year\" ({}), otherwise no timestamp would be accepted.",
+            options.min_year, options.max_year
+        ));
+    }
+
+    if has_separators {
+        options.format =
+            format_with_separators(&date_separator, &time_separator, &datetime_separator);
+    }
+
+    Ok((mode, options, paths))
+}
+
+// Lists every new name wanted by multiple files along with those files, e.g.
+// Error: 3 files would be renamed to "2023-05-14_21-34-06.jpg" in "/photos":
+//     "/photos/IMG_0001.JPG"
+//     "/photos/IMG_0001 (1).JPG"
+//     "/photos/IMG_0002.JPG"
+fn report_conflicts(plan: &RenamePlan) {
+    for ((directory, name), paths) in &plan.conflict_groups {
+        let message = format!(
+            "{} files would be renamed to \"{name}\" in \"{}\"",
+            paths.len(),
+            directory.display()
         );
-        return None;
-    };
 
-    let mut timestamp = timestamp.to_string();
-    // Convert timestamp of format "YYYY:MM:DD HH:MM:SS" to "YYYY-MM-DD_HH-MM-SS"
-    timestamp = timestamp.replace(" ", "_");
-    timestamp = timestamp.replace(":", "-");
+        // Scripts get a line for each of the files instead of a list
+        if log_format() == LogFormat::Json {
+            for path in paths {
+                error!("conflict", Some(path), "{message}");
+            }
+
+            continue;
+        }
+
+        error!("conflict", None, "{message}:");
 
-    Some(timestamp)
+        for path in paths {
+            eprintln!("    \"{}\"", path.display());
+        }
+    }
+
+    if plan.conflict_groups.is_empty() {
+        info!("No files would be renamed to the same name.");
+    } else {
+        error!(
+            "conflicts",
+            None,
+            "Found {} name(s) wanted by multiple files, rename or remove the files above and try again.",
+            plan.conflict_groups.len()
+        );
+    }
+}
+
+// Parses "keep-local", "utc", "local" or an IANA name like "America/Chicago"
+fn parse_timezone(timezone: &str) -> Result<Timezone, String> {
+    match timezone {
+        "keep-local" => Ok(Timezone::KeepLocal),
+        "utc" => Ok(Timezone::Utc),
+        "local" => Ok(Timezone::Local),
+        name => match name.parse() {
+            Ok(tz) => Ok(Timezone::Named(tz)),
+            Err(_) => Err(format!("Error: Unknown timezone \"{timezone}\", expected \"keep-local\", \"utc\", \"local\" or an IANA name like \"America/Chicago\".")),
+        },
+    }
 }
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Epochs this large are in milliseconds, as seconds would be thousands of years from now (5138)
+const MILLISECONDS_THRESHOLD: i64 = 100_000_000_000;
+
+// The timestamps given by "--manifest", in milliseconds since the Unix epoch (UTC)
+// Entries with a path are relative to the manifest, plain names match a file of that name in any folder.
+#[derive(Clone, Default, Debug)]
+pub struct Manifest {
+    paths: HashMap<PathBuf, i64>,
+    names: HashMap<OsString, i64>,
+}
+
+impl Manifest {
+    // The timestamp of the file in milliseconds, or None if the manifest doesn't list it
+    pub fn get(&self, path: &Path) -> Option<i64> {
+        if !self.paths.is_empty() {
+            if let Some(&milliseconds) = fs::canonicalize(path)
+                .ok()
+                .and_then(|path| self.paths.get(&path))
+            {
+                return Some(milliseconds);
+            }
+        }
+
+        self.names.get(path.file_name()?).copied()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+}
+
+// Reads a CSV file of "<file>,<epoch>" lines, where the epoch is in seconds or milliseconds,
+// e.g. "IMG_0001.HEIC,1684100046" or "2023/IMG_0002.HEIC,1684100046123".
+// A header line, empty lines and a UTF-8 byte order mark are ignored, anything else invalid is rejected.
+pub fn load_manifest(path: &Path) -> Result<Manifest, String> {
+    let contents = fs::read_to_string(path).map_err(|error| {
+        format!(
+            "Error: Failed to read the manifest \"{}\" - {error}",
+            path.display()
+        )
+    })?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut manifest = Manifest::default();
+
+    for (index, line) in contents.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        // The epoch comes last, so commas in the name don't need quoting
+        let parsed = line.rsplit_once(',').and_then(|(name, epoch)| {
+            let name = name.trim().trim_matches('"');
+            let epoch = epoch.trim().trim_matches('"').parse::<i64>().ok()?;
+            Some((name, epoch)).filter(|(name, _)| !name.is_empty())
+        });
+        let Some((name, epoch)) = parsed else {
+            // The first line may be a header like "file,timestamp", unless it starts with a file name (e.g. "IMG_0001.HEIC,abc")
+            let first = line
+                .split(',')
+                .next()
+                .unwrap_or(line)
+                .trim()
+                .trim_matches('"');
+
+            if index == 0 && Path::new(first).extension().is_none() {
+                continue;
+            }
+
+            return Err(format!(
+                "Error: Line {} of the manifest \"{}\" should be \"<file>,<epoch>\", but it's \"{line}\".",
+                index + 1,
+                path.display()
+            ));
+        };
+        let milliseconds = if epoch.abs() >= MILLISECONDS_THRESHOLD {
+            epoch
+        } else {
+            epoch.saturating_mul(1000)
+        };
+
+        if name.contains(['/', '\\']) {
+            // Files that don't exist can't be renamed anyway
+            if let Ok(file) = fs::canonicalize(directory.join(name)) {
+                manifest.paths.insert(file, milliseconds);
+            }
+        } else {
+            manifest.names.insert(OsString::from(name), milliseconds);
+        }
+    }
+
+    Ok(manifest)
+}
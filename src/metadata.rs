@@ -0,0 +1,1333 @@
+use crate::{
+    datetime::{
+        convert_timezone, format_datetime, is_unset_datetime, parse_datetime,
+        parse_datetime_with_offset, parse_iso_datetime, parse_offset, parse_subsec, DateTimeParts,
+        Timezone,
+    },
+    error::TimestampError,
+    exiftool::{ExiftoolTags, TagReader},
+    info,
+    options::{sanitize_filename_part, strip_dup_suffix, template_tokens, Options},
+    png::read_png_date,
+    quicktime::{read_creation_time, QUICKTIME_EPOCH_OFFSET},
+};
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    mem,
+    path::{Path, PathBuf},
+    str,
+};
+
+// General Rules of Thumb:
+// EXIF DateTimeOriginal = Photos taken via camera and modern screenshots (after 2020)
+// QuickTime CreationDate = Videos (MOV) taken via camera
+// QuickTime CreateDate = Screen Recordings (MOV/MP4)
+// XMP DateCreated = Older screenshots (before 2020)
+
+// "CreationDate" is actually QuickTime metadata, not EXIF metadata. (https://superuser.com/a/1285932)
+// "-s3" is to just get the value without the header. (https://photo.stackexchange.com/a/56678)
+// Example: "exiftool -DateTimeOriginal -s3 2023-05-25_19-47-30.heic" gives value "2023:05:25 19:47:30"
+// Example: "exiftool -CreationDate -s3 2023-05-14_21-34-06.mov" gives value "2023:05:14 21:34:06-05:00"
+// Example: "exiftool -CreateDate -s3 2023-05-14_21-34-06.mp4" gives value "2023:05:15 02:34:06" (UTC for videos, though without an offset)
+// Example: "exiftool -DateCreated -s3 2019-10-15_02-08-00.png" gives value "2019:10:15 02:08:48"
+// AVI and MKV files don't have QuickTime tags, exiftool exposes their recording date as "DateTimeOriginal" instead.
+
+// Determines which metadata sources are tried for a file, and in what order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MediaKind {
+    // EXIF first, then whatever exiftool can find
+    Photo,
+    // QuickTime based videos (MOV, MP4, M4V, 3GP), read via exiftool
+    QuickTime,
+    // Other video containers (AVI, MKV) which don't have QuickTime tags, read via exiftool
+    OtherVideo,
+}
+
+// A metadata tag which a timestamp can be taken from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DateTag {
+    // Read from EXIF for photos, or via exiftool for other files (e.g. AVI and MKV)
+    DateTimeOriginal,
+    // When a photo was digitized (e.g. scanned), only read from EXIF for photos
+    DateTimeDigitized,
+    CreationDate,
+    CreateDate,
+    MediaCreateDate,
+    DateCreated,
+    // The EXIF GPSDateStamp and GPSTimeStamp of photos, which are UTC
+    GpsDateTime,
+}
+
+impl DateTag {
+    pub const ALL: [DateTag; 7] = [
+        Self::DateTimeOriginal,
+        Self::DateTimeDigitized,
+        Self::CreationDate,
+        Self::CreateDate,
+        Self::MediaCreateDate,
+        Self::DateCreated,
+        Self::GpsDateTime,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DateTimeOriginal => "DateTimeOriginal",
+            Self::DateTimeDigitized => "DateTimeDigitized",
+            Self::CreationDate => "CreationDate",
+            Self::CreateDate => "CreateDate",
+            Self::MediaCreateDate => "MediaCreateDate",
+            Self::DateCreated => "DateCreated",
+            Self::GpsDateTime => "GPSDateTime",
+        }
+    }
+
+    // Looks up a tag by its name, ignoring the case
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|tag| tag.name().eq_ignore_ascii_case(name))
+    }
+
+    // The tags to try in order if the user didn't choose any
+    pub fn defaults(kind: MediaKind) -> &'static [DateTag] {
+        match kind {
+            MediaKind::Photo => &[
+                Self::DateTimeOriginal,
+                Self::DateTimeDigitized,
+                Self::CreationDate,
+                Self::CreateDate,
+                Self::DateCreated,
+                Self::GpsDateTime,
+            ],
+            MediaKind::QuickTime => &[
+                Self::CreationDate,
+                Self::CreateDate,
+                Self::MediaCreateDate,
+                Self::DateCreated,
+            ],
+            MediaKind::OtherVideo => &[
+                Self::DateTimeOriginal,
+                Self::CreateDate,
+                Self::MediaCreateDate,
+            ],
+        }
+    }
+}
+
+// Try exif first (for photos), otherwise use "exiftool" to read miscellaneous metadata (QuickTime, etc.) if it's available
+// The tags are tried in the order given by "--tag-priority", or the defaults for the kind of file.
+// Returns (timestamp, new_stem, source, raw value, date and time) if successful, the extension is added by the caller
+// Returns every error encountered along the way otherwise
+pub fn get_timestamp_and_rename_pair(
+    path: &PathBuf,
+    path_str: &str,
+    kind: MediaKind,
+    options: &Options,
+    prefetched: Option<&ExiftoolTags>,
+    session: &dyn TagReader,
+    exiftool_available: bool,
+) -> Result<(String, String, String, String, NaiveDateTime), Vec<TimestampError>> {
+    let mut exif = LazyExif::new(path, path_str);
+
+    // The manifest is authoritative, so nothing else is read for the files it lists (and the years aren't checked either)
+    if let Some(milliseconds) = options
+        .manifest
+        .as_ref()
+        .and_then(|manifest| manifest.get(path))
+    {
+        let parts =
+            get_timestamp_from_epoch(milliseconds, path_str).map_err(|error| vec![error])?;
+        let raw = parts.raw.clone();
+        // The epoch is UTC, so "keep-local" converts it to the local timezone as the original one is unknown
+        let parts = match options.timezone {
+            Timezone::KeepLocal => convert_timezone(parts, Timezone::Local),
+            _ => parts,
+        };
+        // A file outside of "--after" and "--before" has a timestamp, it just isn't renamed
+        let (timestamp, datetime) = render_timestamp(parts, path, path_str, options, &mut exif)
+            .map_err(|error| vec![error])?;
+        return Ok((
+            timestamp.clone(),
+            timestamp,
+            "manifest".to_string(),
+            raw,
+            datetime,
+        ));
+    }
+
+    let mut errors: Vec<TimestampError> = Vec::new();
+    let mut tags = options
+        .tag_priority
+        .as_deref()
+        .unwrap_or_else(|| DateTag::defaults(kind))
+        .to_vec();
+
+    // "--prefer-gps" trusts the GPS time over the camera's clock, which may have been set wrong
+    if options.prefer_gps {
+        tags.retain(|tag| *tag != DateTag::GpsDateTime);
+        tags.insert(0, DateTag::GpsDateTime);
+    }
+
+    // Reads a tag with exiftool, or from the batched results if the file was part of them
+    let exiftool = |tag, has_timezone| {
+        get_timestamp_from_exiftool(path, path_str, tag, has_timezone, prefetched, session)
+    };
+
+    for tag in tags {
+        // The result along with where it came from and whether the timestamp is UTC (and gets a warning in the filename)
+        let (result, source, utc) = match tag {
+            // The EXIF metadata is read along with the first of these, any other is skipped if it couldn't be
+            DateTag::DateTimeOriginal if kind == MediaKind::Photo => {
+                let Some(metadata) = exif.get() else {
+                    continue;
+                };
+
+                (
+                    metadata.and_then(|exif| {
+                        read_exif_datetime(exif, path_str, ORIGINAL_TAGS, "DateTimeOriginal")
+                    }),
+                    "EXIF DateTimeOriginal",
+                    false,
+                )
+            }
+            // GPS time is UTC, so it only needs a warning if it won't be converted
+            DateTag::GpsDateTime if kind == MediaKind::Photo => {
+                let Some(metadata) = exif.get() else {
+                    continue;
+                };
+
+                (
+                    metadata.and_then(|exif| read_exif_gps_time(exif, path_str)),
+                    "EXIF GPS time",
+                    options.timezone == Timezone::KeepLocal,
+                )
+            }
+            // Scanned or imported photos may only have the date they were digitized
+            DateTag::DateTimeDigitized if kind == MediaKind::Photo => {
+                let Some(metadata) = exif.get() else {
+                    continue;
+                };
+
+                (
+                    metadata.and_then(|exif| {
+                        read_exif_datetime(exif, path_str, DIGITIZED_TAGS, "DateTimeDigitized")
+                    }),
+                    "EXIF DateTimeDigitized",
+                    false,
+                )
+            }
+            // exiftool reports it as "CreateDate", which is tried separately
+            DateTag::DateTimeDigitized => continue,
+            DateTag::GpsDateTime => continue,
+            // PNG files (e.g. iOS screenshots) keep it in text chunks, which can be read without exiftool.
+            // exiftool can still read what isn't supported natively, such as compressed text.
+            DateTag::DateCreated if is_png(path) => match get_timestamp_from_png(path, path_str) {
+                Err(_) if exiftool_available => (
+                    exiftool("DateCreated", false),
+                    "exiftool DateCreated",
+                    false,
+                ),
+                result => (result, "PNG creation time", false),
+            },
+            // Without exiftool, the movie header still has the (UTC) creation time of QuickTime videos
+            DateTag::CreateDate if kind == MediaKind::QuickTime && !exiftool_available => (
+                get_timestamp_from_quicktime_native(path, path_str)
+                    .map(|parts| from_utc(parts, options)),
+                "QuickTime movie header",
+                options.utc_suffix && options.timezone == Timezone::KeepLocal,
+            ),
+            // Don't bother spawning a process that's known to fail
+            _ if !exiftool_available => continue,
+            DateTag::DateTimeOriginal => (
+                exiftool("DateTimeOriginal", false),
+                "exiftool DateTimeOriginal",
+                false,
+            ),
+            DateTag::CreationDate => (
+                exiftool("CreationDate", true),
+                "exiftool CreationDate",
+                false,
+            ),
+            // These are UTC for videos, but not for photos (where it's the EXIF CreateDate)
+            DateTag::CreateDate if kind != MediaKind::Photo && !options.utc_suffix => (
+                exiftool("CreateDate", false).map(|parts| from_utc(parts, options)),
+                "exiftool CreateDate",
+                false,
+            ),
+            DateTag::CreateDate => (
+                exiftool("CreateDate", false),
+                "exiftool CreateDate",
+                kind != MediaKind::Photo,
+            ),
+            DateTag::MediaCreateDate if kind != MediaKind::Photo && !options.utc_suffix => (
+                exiftool("MediaCreateDate", false).map(|parts| from_utc(parts, options)),
+                "exiftool MediaCreateDate",
+                false,
+            ),
+            DateTag::MediaCreateDate => (
+                exiftool("MediaCreateDate", false),
+                "exiftool MediaCreateDate",
+                kind != MediaKind::Photo,
+            ),
+            DateTag::DateCreated => (
+                exiftool("DateCreated", false),
+                "exiftool DateCreated",
+                false,
+            ),
+        };
+
+        match result.and_then(|parts| check_year(parts, source, path_str, options)) {
+            Ok(parts) => {
+                let raw = parts.raw.clone();
+                // A file outside of "--after" and "--before" has a timestamp, it just isn't renamed
+                let (timestamp, datetime) =
+                    render_timestamp(parts, path, path_str, options, &mut exif)
+                        .map_err(|error| vec![error])?;
+
+                if tag == DateTag::DateTimeDigitized {
+                    info!("Note: Using the DateTimeDigitized of \"{path_str}\", which is when it was digitized (e.g. scanned) rather than taken.");
+                }
+
+                if utc {
+                    let stem = format!("{timestamp} (utc)");
+                    return Ok((timestamp, stem, source.to_string(), raw, datetime));
+                }
+
+                return Ok((
+                    timestamp.clone(),
+                    timestamp,
+                    source.to_string(),
+                    raw,
+                    datetime,
+                ));
+            }
+            // A file that can't be opened fails the same way for every source reading it, so only report that once
+            Err(error) => {
+                let unreadable = matches!(
+                    error,
+                    TimestampError::FileOpen { .. } | TimestampError::InvalidExif { .. }
+                );
+
+                if !unreadable
+                    || !errors
+                        .iter()
+                        .any(|existing| mem::discriminant(existing) == mem::discriminant(&error))
+                {
+                    errors.push(error);
+                }
+            }
+        }
+    }
+
+    // Tags given with "--exiftool-tag", for cameras that keep the date somewhere else (e.g. "SonyDateTime")
+    for tag in options.exiftool_tags.iter().filter(|_| exiftool_available) {
+        let source = format!("exiftool {tag}");
+
+        match exiftool(tag, false).and_then(|parts| check_year(parts, &source, path_str, options)) {
+            Ok(parts) => {
+                let raw = parts.raw.clone();
+                // A file outside of "--after" and "--before" has a timestamp, it just isn't renamed
+                let (timestamp, datetime) =
+                    render_timestamp(parts, path, path_str, options, &mut exif)
+                        .map_err(|error| vec![error])?;
+                return Ok((timestamp.clone(), timestamp, source, raw, datetime));
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    // Google Takeout strips the metadata of some files, but keeps the date in a JSON file next to them
+    if options.takeout {
+        let source = "Google Takeout photoTakenTime";
+
+        match get_timestamp_from_takeout(path, path_str)
+            .and_then(|parts| check_year(parts, source, path_str, options))
+        {
+            Ok(parts) => {
+                let raw = parts.raw.clone();
+                // The timestamp is UTC, so "keep-local" converts it to the local timezone as the original one is unknown
+                let parts = match options.timezone {
+                    Timezone::KeepLocal => convert_timezone(parts, Timezone::Local),
+                    _ => parts,
+                };
+                // A file outside of "--after" and "--before" has a timestamp, it just isn't renamed
+                let (timestamp, datetime) =
+                    render_timestamp(parts, path, path_str, options, &mut exif)
+                        .map_err(|error| vec![error])?;
+                return Ok((
+                    timestamp.clone(),
+                    timestamp,
+                    source.to_string(),
+                    raw,
+                    datetime,
+                ));
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    // As a last resort, the modification time is better than nothing (but marked as approximate)
+    if options.mtime_fallback {
+        match get_timestamp_from_mtime(path, path_str)
+            .and_then(|parts| check_year(parts, "modification time", path_str, options))
+        {
+            Ok(parts) => {
+                let raw = parts.raw.clone();
+                // A file outside of "--after" and "--before" has a timestamp, it just isn't renamed
+                let (timestamp, datetime) =
+                    render_timestamp(parts, path, path_str, options, &mut exif)
+                        .map_err(|error| vec![error])?;
+                return Ok((
+                    timestamp.clone(),
+                    format!("{timestamp} (mtime)"),
+                    "modification time".to_string(),
+                    raw,
+                    datetime,
+                ));
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    // Nothing found otherwise
+    Err(errors)
+}
+
+// Every date-related value of a file for "--print-metadata", along with where it came from
+// Unlike when renaming, all sources are read regardless of the kind of file and none of them is picked.
+// The exiftool tags are left out if it isn't available, as they'd only fail the same way.
+pub fn get_all_timestamps(
+    path: &PathBuf,
+    path_str: &str,
+    options: &Options,
+    session: &dyn TagReader,
+    exiftool_available: bool,
+) -> Vec<(String, Result<DateTimeParts, TimestampError>)> {
+    let exiftool = |tag, has_timezone| {
+        get_timestamp_from_exiftool(path, path_str, tag, has_timezone, None, session)
+    };
+    let mut timestamps = vec![
+        (
+            "EXIF DateTimeOriginal".to_string(),
+            get_timestamp_from_exif(path, path_str),
+        ),
+        (
+            "EXIF DateTimeDigitized".to_string(),
+            get_timestamp_from_exif_digitized(path, path_str),
+        ),
+        (
+            "EXIF GPS time".to_string(),
+            get_timestamp_from_exif_gps(path, path_str),
+        ),
+    ];
+
+    if is_png(path) {
+        timestamps.push((
+            "PNG creation time".to_string(),
+            get_timestamp_from_png(path, path_str),
+        ));
+    }
+
+    timestamps.push((
+        "QuickTime movie header".to_string(),
+        get_timestamp_from_quicktime_native(path, path_str),
+    ));
+
+    if exiftool_available {
+        timestamps.extend([
+            (
+                "exiftool DateTimeOriginal".to_string(),
+                exiftool("DateTimeOriginal", false),
+            ),
+            (
+                "exiftool CreationDate".to_string(),
+                exiftool("CreationDate", true),
+            ),
+            (
+                "exiftool CreateDate".to_string(),
+                exiftool("CreateDate", false),
+            ),
+            (
+                "exiftool MediaCreateDate".to_string(),
+                exiftool("MediaCreateDate", false),
+            ),
+            (
+                "exiftool DateCreated".to_string(),
+                exiftool("DateCreated", false),
+            ),
+        ]);
+        timestamps.extend(
+            options
+                .exiftool_tags
+                .iter()
+                .map(|tag| (format!("exiftool {tag}"), exiftool(tag, false))),
+        );
+    }
+
+    if options.takeout {
+        timestamps.push((
+            "Google Takeout photoTakenTime".to_string(),
+            get_timestamp_from_takeout(path, path_str),
+        ));
+    }
+
+    timestamps.push((
+        "modification time".to_string(),
+        get_timestamp_from_mtime(path, path_str),
+    ));
+    timestamps
+}
+
+// Rejects timestamps outside of "--min-year" and "--max-year" as they're most likely corrupt, e.g. a reset clock in 1970
+// The year is checked as it was recorded, before converting it to another timezone.
+fn check_year(
+    parts: DateTimeParts,
+    source: &str,
+    path_str: &str,
+    options: &Options,
+) -> Result<DateTimeParts, TimestampError> {
+    if (options.min_year..=options.max_year).contains(&parts.datetime.year()) {
+        return Ok(parts);
+    }
+
+    Err(TimestampError::ImplausibleYear {
+        path: path_str.to_string(),
+        tag: source.to_string(),
+        datetime: parts.datetime,
+    })
+}
+
+// Converts the timestamp into the requested timezone and renders it with the format, prefix and suffix
+// (and the GPS coordinates and original filename if they're requested).
+// The result is what duplicates are detected by, so it has to include everything but the markers in the final name.
+// Fails if the (converted) timestamp is outside of "--after" and "--before".
+fn render_timestamp(
+    parts: DateTimeParts,
+    path: &Path,
+    path_str: &str,
+    options: &Options,
+    exif: &mut LazyExif,
+) -> Result<(String, NaiveDateTime), TimestampError> {
+    let parts = convert_timezone(parts, options.timezone);
+    let too_early = options.after.is_some_and(|after| parts.datetime < after);
+    let too_late = options
+        .before
+        .is_some_and(|before| parts.datetime >= before);
+
+    if too_early || too_late {
+        return Err(TimestampError::OutOfRange {
+            path: path_str.to_string(),
+            datetime: parts.datetime,
+        });
+    }
+
+    let date = format_datetime(&parts, &options.format);
+    let name = match options.template {
+        Some(ref template) => render_template(template, date, path, path_str, options, exif)?,
+        None => date,
+    };
+    let mut timestamp = format!("{}{name}", options.prefix);
+
+    // Files without GPS metadata just keep the plain timestamp
+    if options.append_gps {
+        if let Some(coordinates) = exif.exif().and_then(read_gps_coordinates) {
+            timestamp = format!("{timestamp}_{}", format_gps_coordinates(coordinates));
+        }
+    }
+
+    if options.keep_original {
+        let original = original_stem(path, &timestamp, options);
+        return Ok((
+            format!("{timestamp}_{original}{}", options.suffix),
+            parts.datetime,
+        ));
+    }
+
+    Ok((format!("{timestamp}{}", options.suffix), parts.datetime))
+}
+
+// Fills in the tokens of "--template", which was validated already. The camera is only read from the EXIF metadata if it's used.
+// Missing values are left empty, unless "--template-strict" is given.
+fn render_template(
+    template: &str,
+    date: String,
+    path: &Path,
+    path_str: &str,
+    options: &Options,
+    exif: &mut LazyExif,
+) -> Result<String, TimestampError> {
+    let tokens = template_tokens(template);
+    let (make, model) = if tokens.contains(&"{camera}") || tokens.contains(&"{make}") {
+        exif.exif().map(read_camera).unwrap_or_default()
+    } else {
+        (None, None)
+    };
+    let mut name = String::new();
+
+    for token in tokens {
+        let (value, token) = match token {
+            "{date}" => (Some(date.clone()), "date"),
+            "{camera}" => (model.clone(), "camera"),
+            "{make}" => (make.clone(), "make"),
+            "{ext}" => (
+                path.extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase()),
+                "ext",
+            ),
+            literal => {
+                name.push_str(literal);
+                continue;
+            }
+        };
+
+        match value {
+            Some(value) => name.push_str(&value),
+            None if options.template_strict => {
+                return Err(TimestampError::MissingTemplateValue {
+                    path: path_str.to_string(),
+                    token,
+                })
+            }
+            None => {}
+        }
+    }
+
+    Ok(name)
+}
+
+// Takes a date and time without an offset (such as the QuickTime CreateDate) as UTC
+// The timezone it was recorded in is unknown, so "keep-local" converts it to the local timezone of this computer instead.
+// With "--utc-suffix", it's kept as UTC (and marked as such by the caller) instead.
+fn from_utc(mut parts: DateTimeParts, options: &Options) -> DateTimeParts {
+    if options.utc_suffix {
+        return parts;
+    }
+
+    parts.offset.get_or_insert(Utc.fix());
+
+    match options.timezone {
+        Timezone::KeepLocal => convert_timezone(parts, Timezone::Local),
+        _ => parts,
+    }
+}
+
+// The original filename (without the extension) for "--keep-original", made safe to use in a filename.
+// Anything a previous run added is removed again, so that running twice doesn't keep stacking timestamps.
+fn original_stem(path: &Path, timestamp: &str, options: &Options) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = strip_suffixes(&stem, options);
+    let (mut original, duplicates) = match stem
+        .strip_prefix(timestamp)
+        .and_then(|original| original.strip_prefix('_'))
+    {
+        Some(original) => (original, true),
+        // Named by a run with other options (or an older version), so only the markers it would add again are removed
+        None => (stem, false),
+    };
+
+    // The markers and duplicate suffixes, e.g. " (utc)" or " (1)", are always at the very end
+    while let Some(stripped) = original
+        .strip_suffix(" (utc)")
+        .or_else(|| original.strip_suffix(" (mtime)"))
+        .or_else(|| strip_dup_suffix(original, &options.dup_format).filter(|_| duplicates))
+    {
+        original = stripped;
+    }
+
+    let original = original.strip_suffix(&options.suffix).unwrap_or(original);
+    sanitize_filename_part(strip_suffixes(original, options))
+}
+
+// Removes everything given with "--strip-suffix" from the end, however many times each one was added
+fn strip_suffixes<'a>(mut stem: &'a str, options: &Options) -> &'a str {
+    while let Some(stripped) = options
+        .strip_suffixes
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix.as_str()))
+    {
+        stem = stripped;
+    }
+
+    stem
+}
+
+// Reads the creation time from the movie header of a QuickTime (MOV, MP4, etc.) file without exiftool
+// It's the same value as exiftool's CreateDate, which is UTC but has no offset of its own.
+pub fn get_timestamp_from_quicktime_native(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    let file = File::open(path).map_err(|source| TimestampError::FileOpen {
+        path: path_str.to_string(),
+        source,
+    })?;
+
+    // A file that can't be parsed doesn't have a usable movie header either
+    let Ok(Some(seconds)) = read_creation_time(BufReader::new(file)) else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: String::from("CreateDate"),
+        });
+    };
+
+    let datetime = i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds - QUICKTIME_EPOCH_OFFSET, 0));
+    let Some(datetime) = datetime else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("CreateDate"),
+            value: seconds.to_string(),
+        });
+    };
+
+    Ok(DateTimeParts {
+        datetime: datetime.naive_utc(),
+        subsec: None,
+        offset: FixedOffset::east_opt(0),
+        raw: seconds.to_string(),
+    })
+}
+
+// The JSON files Google Takeout puts next to each file, e.g. "IMG_1234.jpg.json" for "IMG_1234.jpg"
+// Newer exports name them "IMG_1234.jpg.supplemental-metadata.json" instead, and edited copies
+// (e.g. "IMG_1234-edited.jpg") share the one of the original.
+fn takeout_json_paths(path: &Path) -> Vec<PathBuf> {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return Vec::new();
+    };
+    let mut names = vec![
+        format!("{name}.json"),
+        format!("{name}.supplemental-metadata.json"),
+    ];
+
+    if let Some((stem, extension)) = name.rsplit_once('.') {
+        if let Some(original) = stem.strip_suffix("-edited") {
+            names.push(format!("{original}.{extension}.json"));
+            names.push(format!("{original}.{extension}.supplemental-metadata.json"));
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| path.with_file_name(name))
+        .collect()
+}
+
+// Reads "photoTakenTime.timestamp" (seconds since the Unix epoch, as a string) from the Google Takeout JSON of the file
+pub fn get_timestamp_from_takeout(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    let Some(json_path) = takeout_json_paths(path)
+        .into_iter()
+        .find(|json_path| json_path.is_file())
+    else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: String::from("photoTakenTime"),
+        });
+    };
+    let contents = fs::read(&json_path).map_err(|source| TimestampError::FileOpen {
+        path: json_path.to_string_lossy().to_string(),
+        source,
+    })?;
+    let json: serde_json::Value = serde_json::from_slice(&contents).unwrap_or_default();
+
+    // Older exports store it as a string, but accept a number as well
+    let raw = match &json["photoTakenTime"]["timestamp"] {
+        serde_json::Value::String(raw) => raw.clone(),
+        serde_json::Value::Number(number) => number.to_string(),
+        _ => {
+            return Err(TimestampError::MissingTag {
+                path: path_str.to_string(),
+                tag: String::from("photoTakenTime"),
+            })
+        }
+    };
+    let Some(datetime) = raw
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+    else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("photoTakenTime"),
+            value: raw,
+        });
+    };
+
+    Ok(DateTimeParts {
+        datetime: datetime.naive_utc(),
+        subsec: None,
+        offset: FixedOffset::east_opt(0),
+        raw,
+    })
+}
+
+// Converts milliseconds since the Unix epoch (as given by "--manifest") into a UTC timestamp
+// The milliseconds are only kept if there are any, as whole seconds are usually just given in milliseconds.
+pub fn get_timestamp_from_epoch(
+    milliseconds: i64,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    let Some(datetime) = DateTime::from_timestamp_millis(milliseconds) else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("manifest"),
+            value: milliseconds.to_string(),
+        });
+    };
+    let subsec = milliseconds.rem_euclid(1000);
+
+    Ok(DateTimeParts {
+        datetime: datetime.naive_utc(),
+        subsec: (subsec > 0).then(|| format!("{subsec:03}")),
+        offset: FixedOffset::east_opt(0),
+        raw: milliseconds.to_string(),
+    })
+}
+
+// Returns the filesystem modification time in the local timezone, which is only approximate
+// as it changes whenever the file is edited (or copied without preserving it)
+pub fn get_timestamp_from_mtime(
+    path: &PathBuf,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|source| TimestampError::ModifiedTime {
+            path: path_str.to_string(),
+            source,
+        })?;
+    let modified = DateTime::<Local>::from(modified);
+
+    Ok(DateTimeParts {
+        datetime: modified.naive_local(),
+        subsec: None,
+        offset: Some(modified.offset().fix()),
+        raw: modified.to_rfc3339(),
+    })
+}
+
+// Opens the file and reads its EXIF metadata
+fn read_exif(path: &Path, path_str: &str) -> Result<exif::Exif, TimestampError> {
+    let file = File::open(path).map_err(|source| TimestampError::FileOpen {
+        path: path_str.to_string(),
+        source,
+    })?;
+    let exifreader = exif::Reader::new();
+    // TIFF based files (e.g. DNG and CR2) are read whole, as their EXIF may be anywhere in the file
+    let result = if is_heif(path) {
+        exifreader.read_from_container(&mut BufReader::new(LimitedReader {
+            inner: file,
+            remaining: MAX_HEIF_METADATA,
+        }))
+    } else {
+        exifreader.read_from_container(&mut BufReader::new(file))
+    };
+
+    // Ignore the file if invalid exif
+    let Ok(exif) = result else {
+        return Err(TimestampError::InvalidExif {
+            path: path_str.to_string(),
+        });
+    };
+
+    Ok(exif)
+}
+
+// The EXIF metadata of a file, which its EXIF dates, GPS coordinates and camera are all read from.
+// The file is only opened and parsed the first time any of them is needed.
+struct LazyExif<'a> {
+    path: &'a Path,
+    path_str: &'a str,
+    // The error is taken the first time it's returned
+    state: Option<Result<exif::Exif, Option<TimestampError>>>,
+}
+
+impl<'a> LazyExif<'a> {
+    fn new(path: &'a Path, path_str: &'a str) -> Self {
+        Self {
+            path,
+            path_str,
+            state: None,
+        }
+    }
+
+    // The metadata, or why it couldn't be read. That's the same for every tag, so it's only returned once and None after.
+    fn get(&mut self) -> Option<Result<&exif::Exif, TimestampError>> {
+        let state = self
+            .state
+            .get_or_insert_with(|| read_exif(self.path, self.path_str).map_err(Some));
+
+        match state {
+            Ok(exif) => Some(Ok(exif)),
+            Err(error) => error.take().map(Err),
+        }
+    }
+
+    // The metadata if it could be read, for the values that are optional
+    fn exif(&mut self) -> Option<&exif::Exif> {
+        self.get()?.ok()
+    }
+}
+
+// HEIC photos only need their "meta" box and the EXIF item it points to, which take up a few KB.
+// The exif crate seeks past the image data, but reads boxes (or items) of unspecified length to the end of the file,
+// which for a 48MP photo (or a broken one) would mean buffering all of it to pull a date.
+const MAX_HEIF_METADATA: u64 = 16 * 1024 * 1024;
+
+// Fails once "remaining" bytes have been read, while seeking (e.g. past the image data) is free
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !buffer.is_empty() {
+            return Err(io::Error::other("too much metadata to read"));
+        }
+
+        let length = buffer
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buffer[..length])?;
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for LimitedReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
+
+fn is_heif(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("heic") || extension.eq_ignore_ascii_case("heif")
+    })
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+// Reads the creation time from the XMP or "Creation Time" text chunk of a PNG file
+// It's usually ISO 8601 (e.g. "2019-10-15T02:08:48"), though some programs write
+// "2019:10:15 02:08:48" or RFC 2822 ("Tue, 15 Oct 2019 02:08:48 +0000") instead.
+pub fn get_timestamp_from_png(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    let file = File::open(path).map_err(|source| TimestampError::FileOpen {
+        path: path_str.to_string(),
+        source,
+    })?;
+
+    // A file that can't be read as PNG doesn't have the chunks either
+    let Ok(Some(raw)) = read_png_date(BufReader::new(file)) else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: String::from("DateCreated"),
+        });
+    };
+
+    if let Some(parts) = parse_iso_datetime(&raw).or_else(|| parse_datetime_with_offset(&raw)) {
+        return Ok(parts);
+    }
+
+    match DateTime::parse_from_rfc2822(&raw) {
+        Ok(datetime) => Ok(DateTimeParts {
+            datetime: datetime.naive_local(),
+            subsec: None,
+            offset: Some(*datetime.offset()),
+            raw,
+        }),
+        Err(_) => Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("DateCreated"),
+            value: raw,
+        }),
+    }
+}
+
+// Where the photo was taken as (latitude, longitude) in decimal degrees, if it has GPS metadata
+// Negative values are south and west respectively.
+pub fn get_gps_coordinates(path: &Path) -> Option<(f64, f64)> {
+    read_gps_coordinates(&read_exif(path, &path.to_string_lossy()).ok()?)
+}
+
+fn read_gps_coordinates(exif: &exif::Exif) -> Option<(f64, f64)> {
+    Some((
+        read_gps_degrees(
+            exif,
+            exif::Tag::GPSLatitude,
+            exif::Tag::GPSLatitudeRef,
+            b'S',
+        )?,
+        read_gps_degrees(
+            exif,
+            exif::Tag::GPSLongitude,
+            exif::Tag::GPSLongitudeRef,
+            b'W',
+        )?,
+    ))
+}
+
+// The make and model of the camera that took the photo, e.g. ("Apple", "iPhone-14-Pro"), if it has EXIF metadata
+// Both are made safe to use in a filename, with the spaces replaced by dashes.
+pub fn get_camera(path: &Path) -> (Option<String>, Option<String>) {
+    match read_exif(path, &path.to_string_lossy()) {
+        Ok(exif) => read_camera(&exif),
+        Err(_) => (None, None),
+    }
+}
+
+fn read_camera(exif: &exif::Exif) -> (Option<String>, Option<String>) {
+    let read = |tag| {
+        let field = exif.get_field(tag, exif::In::PRIMARY)?;
+        let exif::Value::Ascii(ref values) = field.value else {
+            return None;
+        };
+        let value = String::from_utf8_lossy(values.first()?);
+        let value = value
+            .trim_matches(|character: char| character == '\0' || character.is_whitespace())
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-");
+
+        Some(sanitize_filename_part(&value)).filter(|value| !value.is_empty())
+    };
+
+    (read(exif::Tag::Make), read(exif::Tag::Model))
+}
+
+// Converts the degrees, minutes and seconds of a GPS coordinate to decimal degrees
+fn read_gps_degrees(
+    exif: &exif::Exif,
+    tag: exif::Tag,
+    reference: exif::Tag,
+    negative: u8,
+) -> Option<f64> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let exif::Value::Rational(ref values) = field.value else {
+        return None;
+    };
+    let [degrees, minutes, seconds] = values.as_slice() else {
+        return None;
+    };
+    let decimal = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+
+    // The reference is "N" or "S" for the latitude and "E" or "W" for the longitude
+    let reference = exif.get_field(reference, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = reference.value else {
+        return None;
+    };
+
+    if values.first()?.first()? == &negative {
+        Some(-decimal)
+    } else {
+        Some(decimal)
+    }
+}
+
+// Renders the coordinates compactly for filenames, e.g. "40.71N_74.00W"
+fn format_gps_coordinates((latitude, longitude): (f64, f64)) -> String {
+    format!(
+        "{:.2}{}_{:.2}{}",
+        latitude.abs(),
+        if latitude < 0.0 { 'S' } else { 'N' },
+        longitude.abs(),
+        if longitude < 0.0 { 'W' } else { 'E' }
+    )
+}
+
+// Returns the parsed DateTimeOriginal (with SubSecTimeOriginal and OffsetTimeOriginal if present) if successful
+// Ignore the entry if unsuccessful
+pub fn get_timestamp_from_exif(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    read_exif_datetime(
+        &read_exif(path, path_str)?,
+        path_str,
+        ORIGINAL_TAGS,
+        "DateTimeOriginal",
+    )
+}
+
+// Do not use DateTime, as that's supposed to update if the image is modified (https://gitlab.gnome.org/GNOME/gimp/-/issues/8160).
+const ORIGINAL_TAGS: [exif::Tag; 3] = [
+    exif::Tag::DateTimeOriginal,
+    exif::Tag::SubSecTimeOriginal,
+    exif::Tag::OffsetTimeOriginal,
+];
+
+// Returns the parsed DateTimeDigitized (with SubSecTimeDigitized and OffsetTimeDigitized if present) if successful
+// It's the same as DateTimeOriginal for photos taken with a camera, but when the photo was scanned for scanned ones.
+pub fn get_timestamp_from_exif_digitized(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    read_exif_datetime(
+        &read_exif(path, path_str)?,
+        path_str,
+        DIGITIZED_TAGS,
+        "DateTimeDigitized",
+    )
+}
+
+const DIGITIZED_TAGS: [exif::Tag; 3] = [
+    exif::Tag::DateTimeDigitized,
+    exif::Tag::SubSecTimeDigitized,
+    exif::Tag::OffsetTimeDigitized,
+];
+
+// Reads a date and time from EXIF along with its fractional seconds and UTC offset: [date and time, subsec, offset]
+fn read_exif_datetime(
+    exif: &exif::Exif,
+    path_str: &str,
+    [datetime_tag, subsec_tag, offset_tag]: [exif::Tag; 3],
+    name: &'static str,
+) -> Result<DateTimeParts, TimestampError> {
+    // Ignore if "ifd_num" isn't "primary", as that indicates that it's a thumbnail image, not a main image.
+    // RAW files (e.g. CR2) often have several of those, each with their own (possibly different) dates.
+    let Some(datetime) = exif.get_field(datetime_tag, exif::In::PRIMARY) else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: name.to_string(),
+        });
+    };
+
+    // The value as stored (e.g. "2023:05:14 21:34:06") rather than how it's displayed,
+    // which is "unknown" for invalid dates. Anything but a string is displayed as is.
+    let stored = match datetime.value {
+        exif::Value::Ascii(ref values) => values
+            .first()
+            .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string()),
+        _ => None,
+    };
+
+    // Falls through to the next source just like a missing tag
+    if stored.as_deref().is_some_and(is_unset_datetime) {
+        return Err(TimestampError::UnsetDate {
+            path: path_str.to_string(),
+            tag: name.to_string(),
+            value: stored.unwrap_or_default(),
+        });
+    }
+
+    // Timestamp of format "YYYY-MM-DD HH:MM:SS"
+    let displayed = datetime.display_value().to_string();
+    let Some(mut parts) = parse_datetime(&displayed) else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: name.to_string(),
+            value: stored.unwrap_or(displayed),
+        });
+    };
+
+    if let Some(stored) = stored {
+        parts.raw = stored;
+    }
+
+    // Some Android phones and Panasonic cameras store the whole "SubSecDateTimeOriginal" (as exiftool calls it)
+    // in the tag itself, e.g. "2023:05:25 19:47:30.123+02:00". The exif crate only reads the first 19 characters,
+    // so the rest is parsed here and takes precedence over the separate tags.
+    if let Some(combined) = parse_datetime_with_offset(&parts.raw) {
+        parts.subsec = combined.subsec;
+        parts.offset = combined.offset;
+    }
+
+    // Fractional seconds are optional, so carry on without them if missing or unusable.
+    // The value is stored as a string of digits (e.g. "123"), though some cameras pad it with spaces.
+    if let Some(subsec) = exif
+        .get_field(subsec_tag, exif::In::PRIMARY)
+        .filter(|_| parts.subsec.is_none())
+    {
+        if let exif::Value::Ascii(ref values) = subsec.value {
+            parts.subsec = values
+                .first()
+                .and_then(|bytes| str::from_utf8(bytes).ok())
+                .and_then(parse_subsec);
+        }
+    }
+
+    // Modern iPhones also record the UTC offset, e.g. "-05:00", which allows converting to UTC.
+    if let Some(offset) = exif
+        .get_field(offset_tag, exif::In::PRIMARY)
+        .filter(|_| parts.offset.is_none())
+    {
+        if let exif::Value::Ascii(ref values) = offset.value {
+            parts.offset = values
+                .first()
+                .and_then(|bytes| str::from_utf8(bytes).ok())
+                .and_then(parse_offset);
+        }
+    }
+
+    Ok(parts)
+}
+
+// If it fails for whatever reason, just ignore the entry
+// The GPS receiver's time is UTC regardless of the camera's clock, so the offset is always known.
+pub fn get_timestamp_from_exif_gps(
+    path: &Path,
+    path_str: &str,
+) -> Result<DateTimeParts, TimestampError> {
+    read_exif_gps_time(&read_exif(path, path_str)?, path_str)
+}
+
+fn read_exif_gps_time(exif: &exif::Exif, path_str: &str) -> Result<DateTimeParts, TimestampError> {
+    let Some(date_field) = exif.get_field(exif::Tag::GPSDateStamp, exif::In::PRIMARY) else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: String::from("GPSDateStamp"),
+        });
+    };
+    let Some(time_field) = exif.get_field(exif::Tag::GPSTimeStamp, exif::In::PRIMARY) else {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: String::from("GPSTimeStamp"),
+        });
+    };
+
+    // The date is a string of format "YYYY:MM:DD"
+    let date = match date_field.value {
+        exif::Value::Ascii(ref values) => values
+            .first()
+            .and_then(|bytes| str::from_utf8(bytes).ok())
+            .and_then(|raw| NaiveDate::parse_from_str(raw.trim(), "%Y:%m:%d").ok()),
+        _ => None,
+    };
+    let Some(date) = date else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("GPSDateStamp"),
+            value: date_field.display_value().to_string(),
+        });
+    };
+
+    // The time is three rationals (hours, minutes and seconds), where the seconds may be fractional
+    let time = match time_field.value {
+        exif::Value::Rational(ref values) if values.len() == 3 => {
+            let seconds = values[2].to_f64();
+            NaiveTime::from_hms_opt(
+                values[0].to_f64() as u32,
+                values[1].to_f64() as u32,
+                seconds as u32,
+            )
+            .map(|time| (time, (seconds.fract() * 1000.0) as u32))
+        }
+        _ => None,
+    };
+    let Some((time, milliseconds)) = time else {
+        return Err(TimestampError::InvalidDate {
+            path: path_str.to_string(),
+            tag: String::from("GPSTimeStamp"),
+            value: time_field.display_value().to_string(),
+        });
+    };
+
+    Ok(DateTimeParts {
+        datetime: date.and_time(time),
+        subsec: (milliseconds > 0).then(|| format!("{milliseconds:03}")),
+        offset: FixedOffset::east_opt(0),
+        raw: format!(
+            "{} {}",
+            date_field.display_value(),
+            time_field.display_value()
+        ),
+    })
+}
+
+// If it fails for whatever reason, just ignore the entry
+// Dates are of format "YYYY:MM:DD HH:MM:SS", e.g. "CreateDate" or "DateTimeOriginal" (for AVI and MKV files).
+// With "has_timezone", it may be followed by an offset such as "-05:00" or "Z" (the QuickTime "CreationDate").
+pub fn get_timestamp_from_exiftool(
+    path: &Path,
+    path_str: &str,
+    tag: &str,
+    has_timezone: bool,
+    prefetched: Option<&ExiftoolTags>,
+    session: &dyn TagReader,
+) -> Result<DateTimeParts, TimestampError> {
+    let raw = read_exiftool_tag(path, path_str, tag, prefetched, session)?;
+
+    if is_unset_datetime(&raw) {
+        return Err(TimestampError::UnsetDate {
+            path: path_str.to_string(),
+            tag: tag.to_string(),
+            value: raw,
+        });
+    }
+
+    let parts = if has_timezone {
+        parse_datetime_with_offset(&raw)
+    } else {
+        parse_datetime(&raw)
+    };
+
+    parts.ok_or_else(|| TimestampError::InvalidDate {
+        path: path_str.to_string(),
+        tag: tag.to_string(),
+        value: raw,
+    })
+}
+
+// Gets the raw value of a tag, either from the batched results (if the file was part of it and has the tag) or by running exiftool
+fn read_exiftool_tag(
+    path: &Path,
+    path_str: &str,
+    tag: &str,
+    prefetched: Option<&ExiftoolTags>,
+    session: &dyn TagReader,
+) -> Result<String, TimestampError> {
+    // The batch leaves out values it couldn't take as text, so a missing tag is read on its own
+    if let Some(value) = prefetched.and_then(|tags| tags.get(tag)) {
+        return Ok(value.clone());
+    }
+
+    let output = session
+        .read_tag(tag, path)
+        .map_err(|source| TimestampError::ExiftoolFailed {
+            path: path_str.to_string(),
+            source,
+        })?;
+
+    // Strip the ending newline (which is "\r\n" on Windows)
+    let slice = output
+        .strip_suffix(b"\n")
+        .map(|slice| slice.strip_suffix(b"\r").unwrap_or(slice))
+        .unwrap_or(&output);
+
+    // Output is empty if metadata attribute doesn't exist
+    if slice.is_empty() {
+        return Err(TimestampError::MissingTag {
+            path: path_str.to_string(),
+            tag: tag.to_string(),
+        });
+    }
+
+    let Ok(raw) = str::from_utf8(slice) else {
+        return Err(TimestampError::Utf8 {
+            path: path_str.to_string(),
+            tag: tag.to_string(),
+            bytes: slice.to_vec(),
+        });
+    };
+
+    Ok(raw.to_string())
+}
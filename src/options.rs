@@ -0,0 +1,521 @@
+use crate::{
+    cache::ExiftoolCache, datetime::Timezone, logging::Verbosity, manifest::Manifest,
+    metadata::DateTag, state::LastRuns,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Datelike, Local, NaiveDate, NaiveDateTime,
+};
+use glob::Pattern;
+use std::{fmt::Write, path::PathBuf};
+
+// Produces "YYYY-MM-DD_HH-MM-SS"
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+// Consumer digital cameras only date back to the 1990s, earlier timestamps are usually a reset clock (e.g. 1970)
+pub const DEFAULT_MIN_YEAR: i32 = 1990;
+
+// Characters that aren't allowed in filenames on at least one common platform
+const ILLEGAL_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+// Builds a format like DEFAULT_FORMAT with custom separators, e.g. "%Y.%m.%d %H.%M.%S" for (".", ".", " ")
+pub fn format_with_separators(
+    date_separator: &str,
+    time_separator: &str,
+    datetime_separator: &str,
+) -> String {
+    // "%" has to be escaped so it isn't mistaken for a specifier
+    let [date, time, datetime] = [date_separator, time_separator, datetime_separator]
+        .map(|separator| separator.replace('%', "%%"));
+
+    format!("%Y{date}%m{date}%d{datetime}%H{time}%M{time}%S")
+}
+
+// Replaces anything that isn't allowed in filenames with "_", e.g. for reusing the original filename
+pub fn sanitize_filename_part(part: &str) -> String {
+    part.chars()
+        .map(|character| {
+            if ILLEGAL_CHARACTERS.contains(&character) || character.is_control() {
+                '_'
+            } else {
+                character
+            }
+        })
+        .collect()
+}
+
+// Splits a comma-separated list of extensions such as "mov, .MP4" into ["mov", "mp4"]
+pub fn parse_extension_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+fn find_illegal_character(part: &str) -> Option<char> {
+    part.chars()
+        .find(|character| ILLEGAL_CHARACTERS.contains(character) || character.is_control())
+}
+
+// Checks that a part of the filename (e.g. a separator or the prefix) can be used in a filename, "name" being what it is
+pub fn validate_filename_part(part: &str, name: &str) -> Result<(), String> {
+    if let Some(character) = find_illegal_character(part) {
+        return Err(format!(
+            "Error: The {name} \"{part}\" contains {character:?}, which isn't allowed in filenames."
+        ));
+    }
+
+    Ok(())
+}
+
+// Checks that a format can be rendered, and that every timestamp it renders can be used in a filename
+// Points out the specifier (e.g. "%T", which gives "21:34:06") or literal text causing the problem.
+pub fn validate_format(format: &str) -> Result<(), String> {
+    // Catch invalid specifiers now rather than panicking when rendering the first file
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("Error: Invalid format string \"{format}\"."));
+    }
+
+    // Timestamps are rendered without their offset, so a date with two-digit fields says enough
+    let sample = NaiveDate::from_ymd_opt(2023, 12, 24)
+        .and_then(|date| date.and_hms_milli_opt(21, 34, 56, 789))
+        .unwrap_or_default();
+
+    for token in format_tokens(format) {
+        if !token.starts_with('%') {
+            if let Some(character) = find_illegal_character(token) {
+                return Err(format!(
+                    "Error: The format \"{format}\" contains {character:?}, which isn't allowed in filenames."
+                ));
+            }
+
+            continue;
+        }
+
+        let mut rendered = String::new();
+
+        if write!(rendered, "{}", sample.format(token)).is_err() {
+            return Err(format!(
+                "Error: \"{token}\" in the format \"{format}\" can't be used, as timestamps don't include the timezone."
+            ));
+        }
+
+        if let Some(character) = find_illegal_character(&rendered) {
+            return Err(format!(
+                "Error: \"{token}\" in the format \"{format}\" gives e.g. \"{rendered}\", which contains {character:?} that isn't allowed in filenames."
+            ));
+        }
+    }
+
+    // The first days of January can still be in the last week of the year before, e.g. 2021-01-01 is in 2020-W53
+    let tokens = format_tokens(format);
+    let has_token = |kind: char| {
+        tokens
+            .iter()
+            .any(|token| token.starts_with('%') && token.ends_with(kind))
+    };
+
+    if has_token('V') && has_token('Y') && !has_token('G') {
+        return Err(format!(
+            "Error: The format \"{format}\" combines the ISO week \"%V\" with the year \"%Y\", use the ISO year \"%G\" instead (e.g. 2021-01-01 is in week 53 of 2020)."
+        ));
+    }
+
+    Ok(())
+}
+
+// Splits a format into its specifiers (e.g. "%d", "%-d", "%:z" or "%.3f") and the literal text in between
+fn format_tokens(format: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = format;
+
+    while !rest.is_empty() {
+        // Specifiers end with a letter (or "%" and "+"), anything before that is padding or precision
+        let end = if rest.starts_with('%') {
+            rest.char_indices()
+                .skip(1)
+                .find(|(_, character)| {
+                    character.is_ascii_alphabetic() || matches!(character, '%' | '+')
+                })
+                .map_or(rest.len(), |(index, character)| {
+                    index + character.len_utf8()
+                })
+        } else {
+            rest.find('%').unwrap_or(rest.len())
+        };
+
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    tokens
+}
+
+// The values a "--template" can use, e.g. "{date}_{camera}"
+pub const TEMPLATE_TOKENS: [&str; 4] = ["date", "camera", "make", "ext"];
+
+// Checks that a template only uses known tokens (including "{date}", or every file would get the same name),
+// and that its literal text can be used in a filename
+pub fn validate_template(template: &str) -> Result<(), String> {
+    let mut has_date = false;
+
+    for token in template_tokens(template) {
+        let Some(name) = token.strip_prefix('{') else {
+            if let Some(character) = find_illegal_character(token) {
+                return Err(format!(
+                    "Error: The template \"{template}\" contains {character:?}, which isn't allowed in filenames."
+                ));
+            }
+
+            continue;
+        };
+        let Some(name) = name.strip_suffix('}') else {
+            return Err(format!(
+                "Error: \"{token}\" in the template \"{template}\" is missing its closing \"}}\"."
+            ));
+        };
+
+        if !TEMPLATE_TOKENS.contains(&name) {
+            return Err(format!(
+                "Error: Unknown token \"{token}\" in the template \"{template}\", it can use \"{{date}}\", \"{{camera}}\", \"{{make}}\" and \"{{ext}}\"."
+            ));
+        }
+
+        has_date |= name == "date";
+    }
+
+    if !has_date {
+        return Err(format!(
+            "Error: The template \"{template}\" has to include \"{{date}}\", otherwise files would get the same name."
+        ));
+    }
+
+    Ok(())
+}
+
+// Splits a template into its tokens (e.g. "{date}") and the literal text in between
+pub fn template_tokens(template: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        let end = if rest.starts_with('{') {
+            rest.find('}').map_or(rest.len(), |index| index + 1)
+        } else {
+            rest.find('{').unwrap_or(rest.len())
+        };
+
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    tokens
+}
+
+// The suffix "--dedupe" adds to the later files with the same name, "{n}" being the number
+pub const DEFAULT_DUP_FORMAT: &str = " ({n})";
+
+// Splits a "--dup-format" into the text before and after its "{n}" (or e.g. "{n:03}"), along with the width it's padded to
+// Returns None if there isn't exactly one placeholder, or if there are other braces.
+fn split_dup_format(format: &str) -> Option<(&str, usize, &str)> {
+    let start = format.find("{n")?;
+    let end = start + format[start..].find('}')?;
+    let width = match &format[start + 2..end] {
+        "" => 0,
+        spec => spec
+            .strip_prefix(":0")?
+            .parse()
+            .ok()
+            .filter(|width| *width <= 9)?,
+    };
+    let (prefix, suffix) = (&format[..start], &format[end + 1..]);
+
+    if prefix.contains(['{', '}']) || suffix.contains(['{', '}']) {
+        return None;
+    }
+
+    Some((prefix, width, suffix))
+}
+
+// Checks that a "--dup-format" has a single number, and that the suffixes it gives can be used in a filename
+// The number has to be set apart from the timestamp, otherwise e.g. "{n}" could turn "..._00-00-01" into "..._00-00-011".
+pub fn validate_dup_format(format: &str) -> Result<(), String> {
+    let Some((prefix, _, suffix)) = split_dup_format(format) else {
+        return Err(format!(
+            "Error: The duplicate format \"{format}\" needs a single \"{{n}}\" (or e.g. \"{{n:03}}\" to pad it with zeros) for the number."
+        ));
+    };
+
+    if prefix.is_empty() || prefix.ends_with(|character: char| character.is_ascii_digit()) {
+        return Err(format!(
+            "Error: The duplicate format \"{format}\" has to put something other than a digit before the number, e.g. \"_{{n:03}}\"."
+        ));
+    }
+
+    validate_filename_part(prefix, "duplicate format")?;
+    validate_filename_part(suffix, "duplicate format")
+}
+
+// Renders the suffix of the "n"th file with the same name, e.g. " (1)" or "_001"
+pub fn format_dup_suffix(format: &str, n: usize) -> String {
+    let Some((prefix, width, suffix)) = split_dup_format(format) else {
+        return format!(" ({n})");
+    };
+
+    format!("{prefix}{n:0width$}{suffix}")
+}
+
+// Removes a suffix added by "--dedupe" from the end of a stem, if it has one
+pub fn strip_dup_suffix<'a>(stem: &'a str, format: &str) -> Option<&'a str> {
+    let (prefix, width, suffix) = split_dup_format(format)?;
+    let number = stem.strip_suffix(suffix)?;
+    let rest = number.trim_end_matches(|character: char| character.is_ascii_digit());
+    let digits = number.len() - rest.len();
+
+    if digits == 0 || digits < width {
+        return None;
+    }
+
+    rest.strip_suffix(prefix)
+}
+
+// The case of the extension in new filenames
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExtensionCase {
+    Lower,
+    Upper,
+    // Keep it as it was, e.g. ".HEIC" for iPhone photos
+    Preserve,
+}
+
+// What happens with "--collision-policy" when a file's new name is already taken by another one
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CollisionPolicy {
+    // Rename nothing at all
+    Error,
+    // Leave the later file as it is
+    Skip,
+    // Add a suffix like " (1)" to the later file, what "--dedupe" does
+    Suffix,
+    // Replace the file that's already there, as long as it isn't being renamed itself (requires "--force")
+    Overwrite,
+}
+
+// The order files are given their new names in with "--sort", which decides who gets which "--dedupe" suffix
+// Ties (and "Name") keep the order of the original paths.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    Name,
+    // Oldest first
+    Timestamp,
+    // Smallest first
+    Size,
+    // Filesystem modification time, oldest first
+    Mtime,
+}
+
+// Subfolders (of the file's folder or "--output-dir") to sort files into by their date
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DateFolders {
+    // E.g. "2023"
+    Year,
+    // E.g. "2023/05"
+    Month,
+    // E.g. "2023/05/25"
+    Day,
+}
+
+impl DateFolders {
+    pub fn path(self, datetime: NaiveDateTime) -> PathBuf {
+        let mut path = PathBuf::from(format!("{:04}", datetime.year()));
+
+        if self != DateFolders::Year {
+            path.push(format!("{:02}", datetime.month()));
+        }
+        if self == DateFolders::Day {
+            path.push(format!("{:02}", datetime.day()));
+        }
+
+        path
+    }
+}
+
+pub struct Options {
+    // Run the whole pipeline but only print what would be renamed.
+    pub dry_run: bool,
+    // Also process the files in every subdirectory.
+    pub recursive: bool,
+    // How many levels of subdirectories "recursive" goes into, or None for all of them.
+    pub max_depth: Option<usize>,
+    // Keep running after renaming, and rename the files showing up in the folders as well.
+    pub watch: bool,
+    // What to do when a new name is already taken, which aborts by default.
+    pub collision_policy: CollisionPolicy,
+    // Skip files with the same timestamp as another file if their contents are identical too, instead of conflicting.
+    pub dedupe_content: bool,
+    // The order files are renamed and listed in, and given their "--dedupe" suffixes in.
+    pub sort: SortOrder,
+    // Only list every group of files sharing a timestamp, without renaming anything.
+    pub simulate_conflicts: bool,
+    // Like "simulate_conflicts", but nothing else is printed (not even the summary), for auditing large archives.
+    pub report_only_conflicts: bool,
+    // Rename everything that doesn't conflict instead of nothing at all, listing the conflicts at the end.
+    pub keep_going: bool,
+    // Accept all warnings without prompting.
+    pub yes: bool,
+    // Never prompt, abort instead if warnings would need confirmation.
+    pub no_interactive: bool,
+    // What pressing Enter at the confirmation prompt answers, or None to ask again.
+    pub default_answer: Option<bool>,
+    // Ask before renaming each file instead of renaming them all at once.
+    pub interactive: bool,
+    // strftime-like format string used to render the new filename.
+    pub format: String,
+    // What the new filename is made of, with "{date}" being the timestamp rendered with the format.
+    pub template: Option<String>,
+    // Skip the files missing a value the template uses (e.g. the camera), instead of leaving it empty.
+    pub template_strict: bool,
+    // The suffix "--dedupe" adds to files with the same name, e.g. " ({n})" or "_{n:03}".
+    pub dup_format: String,
+    // Copy files to their new names instead of moving them, leaving the originals untouched.
+    pub copy: bool,
+    // Place all renamed files in this directory instead of their own.
+    pub output_dir: Option<PathBuf>,
+    // Never run exiftool, only read metadata natively (skipping the files that can't be).
+    pub no_exiftool: bool,
+    // Keep the UTC CreateDate of videos as it is (with a " (utc)" suffix) instead of converting it to the local timezone.
+    pub utc_suffix: bool,
+    // Move files into date subfolders, e.g. "2023/05" for "--by-month".
+    pub date_folders: Option<DateFolders>,
+    // Move files found in subfolders into the folder they were found in, which "--output-dir" always does.
+    pub flat: bool,
+    // How many files to read at the same time, 1 is best for spinning disks.
+    pub jobs: usize,
+    // The exiftool binary to run, looked up on PATH by default.
+    pub exiftool_path: PathBuf,
+    // Which timezone to render timestamps in, if the metadata records the UTC offset.
+    pub timezone: Timezone,
+    // Fall back to the filesystem modification time if the metadata has no usable date.
+    pub mtime_fallback: bool,
+    // Fall back to the JSON files from Google Takeout (e.g. "IMG_1234.jpg.json") if the metadata has no usable date.
+    pub takeout: bool,
+    // How much to print, set with "--quiet" and "--verbose".
+    pub verbosity: Verbosity,
+    // Print a JSON report of every file to stdout, everything else goes to stderr then.
+    pub json: bool,
+    // Added before and after the timestamp, e.g. "IMG_" and "_trip" for "IMG_2023-05-25_19-47-30_trip.heic".
+    pub prefix: String,
+    pub suffix: String,
+    // Keep the original filename after the timestamp, e.g. "2023-05-25_19-47-30_IMG_1234.heic".
+    pub keep_original: bool,
+    // Removed from the end of the original filename for "--keep-original", e.g. " (utc)" from an older version.
+    pub strip_suffixes: Vec<String>,
+    // Whether to lowercase (the default), uppercase or keep the extension as is.
+    pub extension_case: ExtensionCase,
+    // The tags to take the timestamp from in order, or the defaults for each kind of file if not given.
+    pub tag_priority: Option<Vec<DateTag>>,
+    // Try the GPS time of photos before any other tag.
+    pub prefer_gps: bool,
+    // Name Live Photos after the date of their video (usually its CreationDate) instead of the photo's.
+    pub prefer_video_date: bool,
+    // Add the GPS coordinates after the timestamp, e.g. "2023-05-25_19-47-30_40.71N_74.00W.heic".
+    pub append_gps: bool,
+    // Also process hidden files (e.g. dotfiles) found in folders, which are skipped by default.
+    pub include_hidden: bool,
+    // Also process the symbolic links found in folders (and scan the folders they point to), which are skipped by default.
+    pub follow_symlinks: bool,
+    // Run even if another run seems to be going in the same folder, i.e. its lock file is still there.
+    pub force: bool,
+    // Undo every rename (and copy) if one of them fails, so that it's all or nothing.
+    pub transactional: bool,
+    // Remove the folders (inside the given ones) that are empty once the files were moved out of them.
+    pub prune_empty: bool,
+    // Only process files with these (lowercase) extensions, e.g. ["mov", "mp4"], or every extension if not given.
+    pub only: Option<Vec<String>>,
+    // Never process files with these (lowercase) extensions.
+    pub exclude: Vec<String>,
+    // Only process files whose name matches any of these globs (ignoring case), e.g. "IMG_*.HEIC", or every file if empty.
+    pub patterns: Vec<Pattern>,
+    // Only rename files taken at or after this date and time, e.g. "2023-01-01 00:00:00".
+    pub after: Option<NaiveDateTime>,
+    // Only rename files taken before this date and time.
+    pub before: Option<NaiveDateTime>,
+    // Additional exiftool tags to try after the others, e.g. "SonyDateTime" (reported as "exiftool SonyDateTime").
+    pub exiftool_tags: Vec<String>,
+    // Timestamps outside of these years (inclusive) are taken as corrupt, so the next source is tried instead.
+    pub min_year: i32,
+    pub max_year: i32,
+    // Timestamps to use instead of the metadata for the files listed in it, from "--manifest".
+    pub manifest: Option<Manifest>,
+    // What exiftool read in previous runs, from "--cache".
+    pub cache: Option<ExiftoolCache>,
+    // When the previous runs finished, from "--since-last-run". Only the files modified since are renamed then.
+    pub last_runs: Option<LastRuns>,
+    // Write "<new name>.ios2exif.json" next to each renamed file with its original name and where the timestamp came from.
+    pub sidecar: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            recursive: false,
+            max_depth: None,
+            watch: false,
+            collision_policy: CollisionPolicy::Error,
+            dedupe_content: false,
+            sort: SortOrder::Timestamp,
+            simulate_conflicts: false,
+            report_only_conflicts: false,
+            keep_going: false,
+            yes: false,
+            no_interactive: false,
+            default_answer: None,
+            interactive: false,
+            format: String::from(DEFAULT_FORMAT),
+            template: None,
+            dup_format: String::from(DEFAULT_DUP_FORMAT),
+            template_strict: false,
+            copy: false,
+            output_dir: None,
+            date_folders: None,
+            flat: false,
+            utc_suffix: false,
+            no_exiftool: false,
+            jobs: 1,
+            exiftool_path: PathBuf::from("exiftool"),
+            timezone: Timezone::KeepLocal,
+            mtime_fallback: false,
+            takeout: false,
+            verbosity: Verbosity::Normal,
+            json: false,
+            prefix: String::new(),
+            suffix: String::new(),
+            keep_original: false,
+            strip_suffixes: Vec::new(),
+            extension_case: ExtensionCase::Lower,
+            tag_priority: None,
+            prefer_gps: false,
+            prefer_video_date: false,
+            append_gps: false,
+            include_hidden: false,
+            follow_symlinks: false,
+            force: false,
+            transactional: false,
+            prune_empty: false,
+            only: None,
+            exclude: Vec::new(),
+            patterns: Vec::new(),
+            after: None,
+            before: None,
+            exiftool_tags: Vec::new(),
+            min_year: DEFAULT_MIN_YEAR,
+            // Allows for cameras whose clock is a little ahead around new year
+            max_year: Local::now().year() + 1,
+            manifest: None,
+            cache: None,
+            last_runs: None,
+            sidecar: false,
+        }
+    }
+}
@@ -0,0 +1,2109 @@
+use crate::{
+    cache::CachedTagReader,
+    error,
+    error::TimestampError,
+    exiftool::{is_exiftool_available, read_tags_batch, ExiftoolSession, TagReader, BATCH_TAGS},
+    info, is_enabled,
+    lock::is_lock_file,
+    logging::Verbosity,
+    metadata::{get_timestamp_and_rename_pair, MediaKind},
+    options::{format_dup_suffix, CollisionPolicy, ExtensionCase, Options, SortOrder},
+    sniff::sniff_extension,
+    verbose, warning,
+};
+use chrono::NaiveDateTime;
+use glob::MatchOptions;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    io::{self, IsTerminal},
+    iter,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+// A file renamed along with a photo: (path, extension, source), e.g. the video of a Live Photo
+type Companion = (PathBuf, Option<String>, String);
+
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub new_path: PathBuf,
+    // What the new name is made of, e.g. "2023-05-14_21-34-06" (or "2023-05-14_21-34-06 (1)" with "--dedupe")
+    pub timestamp: String,
+    // Which metadata the timestamp came from, e.g. "EXIF DateTimeOriginal"
+    pub source: String,
+    // The date as it was read from the metadata, e.g. "2023:05:14 21:34:06-05:00"
+    pub raw: String,
+    pub status: Status,
+    // Where the file comes in the "--sort" order, which is the order files are renamed and listed in
+    pub position: usize,
+    // Files renamed along with this one to the same name (with their own extension), i.e. the video of a Live Photo
+    pub companions: Vec<FileInfo>,
+}
+
+// What happened (or is going to happen) to a file in the plan
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Planned,
+    AlreadyNamed,
+    Renamed,
+    Copied,
+    // Not renamed since it was declined with "--interactive"
+    Declined,
+    // Not renamed since another file wants its new name too, with "--keep-going"
+    Conflicting,
+    // Renaming (or copying) failed with this error
+    Failed(String),
+    // Renamed (or copied), then undone by "--transactional" since another file failed
+    RolledBack,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Planned => "planned",
+            Self::AlreadyNamed => "already-named",
+            Self::Renamed => "renamed",
+            Self::Copied => "copied",
+            Self::Declined => "declined",
+            Self::Conflicting => "conflicting",
+            Self::Failed(_) => "failed",
+            Self::RolledBack => "rolled-back",
+        }
+    }
+}
+
+impl FileInfo {
+    // Whether the file already has its new name, so there's nothing to do
+    pub fn is_already_named(&self) -> bool {
+        self.path == self.new_path
+    }
+
+    // Whether the file still has to be renamed, i.e. it doesn't have its new name yet and wasn't declined
+    pub fn is_pending(&self) -> bool {
+        !self.is_already_named() && !matches!(self.status, Status::Declined | Status::Conflicting)
+    }
+
+    // Marks the file and its companions as declined so they aren't renamed, returning how many files that is
+    pub fn decline(&mut self) -> usize {
+        self.skip(&Status::Declined)
+    }
+
+    // Marks the file and its companions with a status that keeps them from being renamed
+    fn skip(&mut self, status: &Status) -> usize {
+        let mut skipped = 0;
+
+        if !self.is_already_named() {
+            self.status = status.clone();
+            skipped += 1;
+        }
+
+        skipped
+            + self
+                .companions
+                .iter_mut()
+                .map(|companion| companion.skip(status))
+                .sum::<usize>()
+    }
+
+    // The file itself followed by its companions
+    pub fn with_companions(&self) -> impl Iterator<Item = &FileInfo> {
+        iter::once(self).chain(&self.companions)
+    }
+}
+
+pub struct RenamePlan {
+    // Map<(directory, new file name), file>, with the name in lowercase (see "name_key").
+    // Only the files themselves are keyed, their companions' names are claimed as well but don't have an entry.
+    pub entries: BTreeMap<(PathBuf, String), FileInfo>,
+    // Set if any warnings were printed that the user should confirm before renaming.
+    pub needs_confirmation: bool,
+    // Set if any conflicting timestamps or destinations were found, nothing should be renamed then.
+    pub has_conflicts: bool,
+    // Map<(directory, new file name), every file wanting that name>, only for names wanted by multiple files.
+    pub conflict_groups: BTreeMap<(PathBuf, String), Vec<PathBuf>>,
+    // Set if some files need exiftool but it isn't available, those are skipped then.
+    pub exiftool_missing: bool,
+    // What happened to every file so far, "apply_plan" adds the outcome of the renames.
+    pub summary: Summary,
+}
+
+impl RenamePlan {
+    // Every file in the plan (without their companions) in the "--sort" order
+    pub fn files(&self) -> Vec<&FileInfo> {
+        let mut files: Vec<&FileInfo> = self.entries.values().collect();
+        files.sort_by_key(|info| info.position);
+        files
+    }
+
+    pub fn files_mut(&mut self) -> Vec<&mut FileInfo> {
+        let mut files: Vec<&mut FileInfo> = self.entries.values_mut().collect();
+        files.sort_by_key(|info| info.position);
+        files
+    }
+
+    // Marks the files that claimed a name wanted by other files as conflicting too, so "--keep-going" renames the rest
+    // Which of them got the name first is just the order they were found in, so none of them get it.
+    // Companions go along with their photo. Returns how many files that is, the others weren't planned to begin with.
+    pub fn skip_conflicts(&mut self) -> usize {
+        let owners: HashSet<PathBuf> = self
+            .conflict_groups
+            .values()
+            .filter_map(|paths| paths.first().cloned())
+            .collect();
+        let mut skipped = 0;
+
+        for info in self.entries.values_mut() {
+            if info
+                .with_companions()
+                .any(|info| owners.contains(&info.path))
+            {
+                skipped += info.skip(&Status::Conflicting);
+            }
+        }
+
+        skipped
+    }
+
+    // Every file in the plan as a JSON array of {original, new_name, timestamp, source, status} objects,
+    // with an additional "error" if renaming failed
+    pub fn to_json(&self) -> Value {
+        self.files()
+            .into_iter()
+            .flat_map(FileInfo::with_companions)
+            .map(|info| {
+                let mut object = json!({
+                    "original": info.path.to_string_lossy(),
+                    "new_name": info.new_path.to_string_lossy(),
+                    "timestamp": info.timestamp,
+                    "source": info.source,
+                    "raw": info.raw,
+                    "status": info.status.as_str(),
+                });
+
+                if let Status::Failed(ref error) = info.status {
+                    object["error"] = json!(error);
+                }
+
+                object
+            })
+            .collect()
+    }
+}
+
+// A tally of what happened to the files, printed at the end
+#[derive(Clone, Default, Debug)]
+pub struct Summary {
+    // Every file found, whether it's supported or not
+    pub processed: usize,
+    pub renamed: usize,
+    pub copied: usize,
+    // Files that already had their new name
+    pub already_named: usize,
+    // Files without a usable timestamp
+    pub no_metadata: usize,
+    // Files with an unsupported extension
+    pub unsupported: usize,
+    // Files whose timestamp is outside of "--after" and "--before"
+    pub out_of_range: usize,
+    // Files identical to another file with the same timestamp, only with "--dedupe-content"
+    pub duplicates: usize,
+    // Files declined with "--interactive"
+    pub declined: usize,
+    pub warnings: usize,
+    pub conflicts: usize,
+    // Files that couldn't be renamed (or copied)
+    pub failed: usize,
+    // Files whose rename (or copy) was undone with "--transactional"
+    pub rolled_back: usize,
+    // Folders left empty by moving the files out of them, which were removed with "--prune-empty"
+    pub pruned: usize,
+    // Files not modified since the last run with "--since-last-run", which aren't counted as processed either
+    pub unchanged: usize,
+}
+
+impl fmt::Display for Summary {
+    // E.g. "Processed 120 file(s): 110 renamed, 5 skipped (no metadata), 3 warnings, 2 conflicts"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = if self.copied > 0 {
+            vec![format!("{} copied", self.copied)]
+        } else {
+            vec![format!("{} renamed", self.renamed)]
+        };
+
+        if self.already_named > 0 {
+            parts.push(format!("{} already named", self.already_named));
+        }
+        if self.no_metadata > 0 {
+            parts.push(format!("{} skipped (no metadata)", self.no_metadata));
+        }
+        if self.unsupported > 0 {
+            parts.push(format!("{} skipped (unsupported)", self.unsupported));
+        }
+        if self.out_of_range > 0 {
+            parts.push(format!("{} skipped (out of range)", self.out_of_range));
+        }
+        if self.duplicates > 0 {
+            parts.push(format!("{} skipped (duplicate)", self.duplicates));
+        }
+        if self.declined > 0 {
+            parts.push(format!("{} skipped (declined)", self.declined));
+        }
+        if self.failed > 0 {
+            parts.push(format!("{} failed", self.failed));
+        }
+        if self.rolled_back > 0 {
+            parts.push(format!("{} rolled back", self.rolled_back));
+        }
+
+        if self.pruned > 0 {
+            parts.push(format!("{} empty folder(s) removed", self.pruned));
+        }
+
+        parts.push(format!("{} warnings", self.warnings));
+        parts.push(format!("{} conflicts", self.conflicts));
+
+        write!(
+            f,
+            "Processed {} file(s): {}",
+            self.processed,
+            parts.join(", ")
+        )
+    }
+}
+
+// Figures out the new name of every supported file in the given paths without touching anything
+pub fn plan_renames(paths: &[PathBuf], options: &Options) -> Result<RenamePlan, String> {
+    let mut plan = RenamePlan {
+        entries: BTreeMap::new(),
+        needs_confirmation: false,
+        has_conflicts: false,
+        conflict_groups: BTreeMap::new(),
+        exiftool_missing: false,
+        summary: Summary::default(),
+    };
+    let max_depth = if options.recursive {
+        options.max_depth.unwrap_or(usize::MAX)
+    } else {
+        0
+    };
+    let mut files = collect_files(
+        paths,
+        max_depth,
+        options.include_hidden,
+        options.follow_symlinks,
+        &mut plan.summary.warnings,
+    )?;
+
+    // Anything unreadable might be a file that should've been renamed
+    if plan.summary.warnings > 0 {
+        plan.needs_confirmation = true;
+    }
+
+    // Files filtered out by "--only", "--exclude" and "--pattern" are left alone entirely, as if they weren't there
+    files.retain(|path| {
+        if is_sidecar(path) {
+            verbose!("Skipping \"{}\", it's a sidecar file", path.display());
+            return false;
+        }
+
+        if is_lock_file(path) {
+            verbose!("Skipping \"{}\", it's a lock file", path.display());
+            return false;
+        }
+
+        // The Google Takeout JSON files are only read along with the file they belong to
+        if options.takeout && is_takeout_json(path) {
+            verbose!(
+                "Skipping \"{}\", it's a Google Takeout JSON file",
+                path.display()
+            );
+            return false;
+        }
+
+        if !is_extension_selected(path, options) {
+            verbose!(
+                "Skipping \"{}\", its extension isn't selected",
+                path.display()
+            );
+            return false;
+        }
+
+        let matches = is_pattern_matched(path, options);
+
+        if !matches {
+            verbose!(
+                "Skipping \"{}\", its name doesn't match \"--pattern\"",
+                path.display()
+            );
+        }
+
+        matches
+    });
+
+    // Files that weren't modified since the last run were already renamed (or left alone) then, and don't count at all
+    if let Some(since) = options
+        .last_runs
+        .as_ref()
+        .and_then(|last_runs| last_runs.since(paths))
+    {
+        let count = files.len();
+        files.retain(|path| {
+            let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+            let changed = modified.map_or(true, |modified| modified >= since);
+
+            if !changed {
+                verbose!(
+                    "Skipping \"{}\", it wasn't modified since the last run",
+                    path.display()
+                );
+            }
+
+            changed
+        });
+        plan.summary.unchanged = count - files.len();
+
+        if plan.summary.unchanged > 0 {
+            info!(
+                "Note: Skipping {} file(s) that weren't modified since the last run.",
+                plan.summary.unchanged
+            );
+        }
+    }
+
+    plan.summary.processed = files.len();
+    // Process files by their original path so that the output (and suffix assignment) is stable across runs
+    files.sort();
+
+    // Figure out which files are supported (and how) before reading anything
+    let mut supported = Vec::new();
+    // The edits iOS saves next to an edited photo (e.g. "IMG_1234.AAE"), which follow the photo they belong to
+    let mut edits = Vec::new();
+
+    for path in files {
+        let path_str = path.to_string_lossy().to_string();
+        // Get the extension (if any), the case is only changed when building the new name
+        let extension = match path.extension() {
+            Some(extension) => extension.to_string_lossy().to_string(),
+            // Otherwise a file would be renamed to just the timestamp, which doesn't tell what it is anymore
+            None => match sniff_extension(&path) {
+                Some(extension) => {
+                    verbose!("\"{path_str}\" doesn't have an extension, but looks like a .{extension} file");
+                    extension.to_string()
+                }
+                None => {
+                    warning!(
+                        "unknown_file_type",
+                        Some(&path),
+                        "\"{path_str}\" doesn't have an extension and its type can't be told from its contents, ignoring..."
+                    );
+                    plan.summary.unsupported += 1;
+                    plan.summary.warnings += 1;
+                    continue;
+                }
+            },
+        };
+
+        // Determine what to do based on the lowercase file extension
+        let kind = match extension.to_lowercase().as_str() {
+            // Photos
+            "jpg" => MediaKind::Photo,
+            "jpeg" => MediaKind::Photo,
+            "png" => MediaKind::Photo,
+            "heic" => MediaKind::Photo,
+            "heif" => MediaKind::Photo,
+            "tif" => MediaKind::Photo,
+            "tiff" => MediaKind::Photo,
+            "dng" => MediaKind::Photo,
+            "webp" => MediaKind::Photo,
+            // RAW photos from Canon, Nikon and Sony, which are TIFF files underneath
+            "cr2" => MediaKind::Photo,
+            "nef" => MediaKind::Photo,
+            "arw" => MediaKind::Photo,
+            // Videos
+            "mov" => MediaKind::QuickTime,
+            "mp4" => MediaKind::QuickTime,
+            "m4v" => MediaKind::QuickTime,
+            "3gp" => MediaKind::QuickTime,
+            "3gpp" => MediaKind::QuickTime,
+            "avi" => MediaKind::OtherVideo,
+            "mkv" => MediaKind::OtherVideo,
+            "aae" => {
+                edits.push((path, Some(extension)));
+                continue;
+            }
+            _ => {
+                warning!(
+                    "unsupported_extension",
+                    Some(&path),
+                    "Unsupported extension \".{extension}\", ignoring..."
+                );
+                plan.summary.unsupported += 1;
+                plan.summary.warnings += 1;
+                continue;
+            }
+        };
+
+        supported.push((path, path_str, Some(extension), kind));
+    }
+
+    // Check for exiftool once instead of failing to spawn it for every single file
+    // "--no-exiftool" never runs it at all, not even to check whether it's there.
+    let exiftool_available = !options.no_exiftool
+        && (supported.is_empty() || is_exiftool_available(&options.exiftool_path));
+
+    // QuickTime videos can still be read natively, just not as thoroughly
+    if !exiftool_available {
+        // Unless the manifest has their timestamp, that is
+        let needs_exiftool = |(path, _, _, kind): &(PathBuf, String, Option<String>, MediaKind)| {
+            *kind == MediaKind::OtherVideo
+                && !options
+                    .manifest
+                    .as_ref()
+                    .is_some_and(|manifest| manifest.contains(path))
+        };
+        let count = supported.iter().filter(|file| needs_exiftool(file)).count();
+
+        if count > 0 && options.no_exiftool {
+            // Asked for, so it's neither a warning nor a reason to ask for confirmation
+            info!("Note: Skipping {count} video file(s) that can only be read with exiftool, since \"--no-exiftool\" was passed.");
+            plan.summary.unsupported += count;
+            supported.retain(|file| !needs_exiftool(file));
+        } else if count > 0 {
+            warning!(
+                "exiftool_missing",
+                None,
+                "\"{}\" isn't installed (or isn't on PATH), skipping {count} video file(s)...",
+                options.exiftool_path.display()
+            );
+            plan.needs_confirmation = true;
+            plan.exiftool_missing = true;
+            plan.summary.warnings += 1;
+            supported.retain(|file| !needs_exiftool(file));
+        }
+    }
+
+    // Reading the metadata is what takes a while for large folders, so show how far along it is.
+    // Only when a person is looking at it though, and not while output is suppressed or has to stay machine-readable.
+    let progress = if !options.json
+        && is_enabled(Verbosity::Normal)
+        && io::stdout().is_terminal()
+        && io::stderr().is_terminal()
+    {
+        ProgressBar::new(supported.len() as u64).with_style(
+            ProgressStyle::with_template("Reading metadata [{bar:40}] {pos}/{len} files")
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> "),
+        )
+    } else {
+        ProgressBar::hidden()
+    };
+
+    // Videos always go through exiftool, so read all of them with a single process
+    // (except those that aren't valid UTF-8, which are read one at a time instead)
+    let mut videos: Vec<PathBuf> = supported
+        .iter()
+        .filter(|(path, _, _, kind)| *kind != MediaKind::Photo && path.to_str().is_some())
+        .map(|(path, _, _, _)| path.clone())
+        .collect();
+    let tags: Vec<&str> = BATCH_TAGS
+        .into_iter()
+        .chain(options.exiftool_tags.iter().map(String::as_str))
+        .collect();
+    let mut prefetched = HashMap::new();
+
+    // Videos that haven't changed since they were cached with "--cache" don't have to be read again
+    if let Some(ref cache) = options.cache {
+        videos.retain(|path| match cache.get_all(path, &tags) {
+            Some(values) => {
+                prefetched.insert(path.clone(), values);
+                false
+            }
+            None => true,
+        });
+    }
+
+    if !videos.is_empty() && exiftool_available {
+        match read_tags_batch(&options.exiftool_path, &videos, &tags) {
+            Ok(batch) => {
+                // The tags a video doesn't have are cached too, as the batch read all of them
+                if let Some(ref cache) = options.cache {
+                    for (path, values) in &batch {
+                        for tag in &tags {
+                            cache.insert(path, tag, values.get(*tag).cloned().unwrap_or_default());
+                        }
+                    }
+                }
+
+                prefetched.extend(batch);
+            }
+            Err(error) => {
+                warning!(
+                    "exiftool_batch_failed",
+                    None,
+                    "Failed to read all videos at once ({error}), falling back to one file at a time..."
+                );
+                plan.summary.warnings += 1;
+            }
+        }
+    }
+
+    // Anything not covered by the batch (e.g. photos without EXIF) goes through one long-lived process
+    let session = CachedTagReader {
+        reader: ExiftoolSession::new(&options.exiftool_path),
+        cache: options.cache.as_ref(),
+    };
+    let results = run_in_parallel(&supported, options.jobs, |(path, path_str, _, kind)| {
+        let result = get_timestamp_and_rename_pair(
+            path,
+            path_str,
+            *kind,
+            options,
+            prefetched.get(path),
+            &session,
+            exiftool_available,
+        );
+        progress.inc(1);
+        result
+    });
+    progress.finish_and_clear();
+
+    // Live Photos are a photo and a video with the same name (e.g. "IMG_1234.HEIC" and "IMG_1234.MOV"),
+    // the video follows the photo so the pair keeps matching names (under the photo's timestamp).
+    let photos: HashSet<PathBuf> = supported
+        .iter()
+        .zip(&results)
+        .filter(|((_, _, _, kind), result)| {
+            *kind == MediaKind::Photo && matches!(result, Ok(Ok(_)))
+        })
+        .map(|((path, _, _, _), _)| path.with_extension(""))
+        .collect();
+    let mut companions: HashMap<PathBuf, Vec<Companion>> = HashMap::new();
+
+    // The edits reference the photo by name, so they're renamed along with it to stay linked.
+    // Without the photo there's nothing to name them after, so they're left alone.
+    for (path, extension) in edits {
+        if photos.contains(&path.with_extension("")) {
+            verbose!("Keeping \"{}\" together with its photo", path.display());
+            companions
+                .entry(path.with_extension(""))
+                .or_default()
+                .push((path, extension, "AAE edits".to_string()));
+        } else {
+            verbose!(
+                "Skipping \"{}\", there's no photo to rename it with",
+                path.display()
+            );
+            plan.summary.unsupported += 1;
+        }
+    }
+
+    // Results are in the same order as the files regardless of which thread finished first,
+    // so conflict detection and suffix assignment stay deterministic.
+    let mut renamable = Vec::new();
+    // The dates of the Live Photo videos for "--prefer-video-date", Map<path without the extension, result>
+    let mut video_dates = HashMap::new();
+
+    for ((path, path_str, extension, kind), result) in supported.into_iter().zip(results) {
+        // A bug rather than something wrong with the file, so it's a failure instead of a warning
+        let result = match result {
+            Ok(result) => result,
+            Err(message) => {
+                error!(
+                    "read_failed",
+                    Some(&path),
+                    "Reading \"{path_str}\" stopped unexpectedly ({message}). Not renaming..."
+                );
+                plan.summary.failed += 1;
+                continue;
+            }
+        };
+
+        if kind == MediaKind::QuickTime && photos.contains(&path.with_extension("")) {
+            verbose!("Keeping \"{path_str}\" together with its Live Photo");
+
+            if options.prefer_video_date {
+                if let Ok(result) = result {
+                    video_dates.insert(path.with_extension(""), result);
+                }
+            }
+
+            companions
+                .entry(path.with_extension(""))
+                .or_default()
+                .push((path, extension, "Live Photo".to_string()));
+            continue;
+        }
+
+        let (timestamp, stem, source, raw, datetime) = match result {
+            Ok(result) => result,
+            // Left alone on purpose, so it's neither a warning nor a reason to ask for confirmation
+            Err(errors) if matches!(errors.as_slice(), [TimestampError::OutOfRange { .. }]) => {
+                verbose!("Skipping \"{path_str}\", its timestamp is outside of the date range");
+                plan.summary.out_of_range += 1;
+                continue;
+            }
+            Err(errors) => {
+                for error in errors {
+                    warning!(&error.code(), Some(&path), "{error}");
+                }
+
+                warning!(
+                    "no_timestamp",
+                    Some(&path),
+                    "No usable timestamp found for \"{path_str}\". Not renaming..."
+                );
+                plan.needs_confirmation = true;
+                plan.summary.no_metadata += 1;
+                plan.summary.warnings += 1;
+                continue;
+            }
+        };
+
+        renamable.push((
+            path,
+            path_str,
+            extension,
+            timestamp,
+            stem,
+            source,
+            raw,
+            datetime,
+            Vec::new(),
+        ));
+    }
+
+    for (path, path_str, _, timestamp, stem, source, raw, datetime, videos) in &mut renamable {
+        if let Some(paired) = companions.remove(&path.with_extension("")) {
+            *videos = paired;
+        }
+
+        // The video's clock can be more reliable than the photo's, so both are named after the video then
+        if let Some(result) = video_dates.remove(&path.with_extension("")) {
+            verbose!(
+                "Using the {} of the Live Photo video for \"{path_str}\"",
+                result.2
+            );
+            (*timestamp, *stem, *source, *raw, *datetime) = result;
+        }
+    }
+
+    // Which of the files sharing a timestamp gets which "--dedupe" suffix, they're already sorted by path for "name" and ties
+    match options.sort {
+        SortOrder::Name => {}
+        SortOrder::Timestamp => renamable.sort_by_key(|(.., datetime, _)| *datetime),
+        SortOrder::Size => renamable.sort_by_cached_key(|(path, ..)| {
+            fs::metadata(path).map(|metadata| metadata.len()).ok()
+        }),
+        SortOrder::Mtime => renamable.sort_by_cached_key(|(path, ..)| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        }),
+    }
+
+    // Burst photos are taken within the same second but share a "BurstUUID", so they're a sequence rather than a conflict.
+    // They're numbered in the "--sort" order, e.g. "2023-05-14_21-34-06_burst01.heic" and "2023-05-14_21-34-06_burst02.heic".
+    // Only the files wanting the same name are checked, so that exiftool isn't run for every photo.
+    if exiftool_available {
+        let mut groups: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+
+        for (index, (path, _, extension, _, stem, _, _, datetime, _)) in
+            renamable.iter().enumerate()
+        {
+            let directory = destination_directory(path, paths, *datetime, options);
+            let name = file_name(stem, extension, options).to_lowercase();
+            groups.entry((directory, name)).or_default().push(index);
+        }
+
+        for group in groups.into_values().filter(|group| group.len() > 1) {
+            let mut bursts: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+            for index in group {
+                if let Some(uuid) = read_burst_uuid(&session, &renamable[index].0) {
+                    bursts.entry(uuid).or_default().push(index);
+                }
+            }
+
+            for members in bursts.into_values().filter(|members| members.len() > 1) {
+                for (number, index) in members.into_iter().enumerate() {
+                    let (path, _, _, timestamp, stem, _, _, _, _) = &mut renamable[index];
+                    verbose!("\"{}\" is photo {} of a burst", path.display(), number + 1);
+                    *timestamp = format!("{timestamp}_burst{:02}", number + 1);
+                    *stem = format!("{stem}_burst{:02}", number + 1);
+                }
+            }
+        }
+    }
+
+    // Files that already have their new name claim it first (the sort is stable, so the order is kept otherwise),
+    // as re-running with "--dedupe" would otherwise shuffle the suffixes around.
+    renamable.sort_by_key(|(path, _, extension, _, stem, _, _, datetime, _)| {
+        destination_directory(path, paths, *datetime, options)
+            .join(file_name(stem, extension, options))
+            != *path
+    });
+
+    // Every new name taken so far, including those of companions, Map<(directory, new file name), original path>
+    let mut claimed = HashMap::new();
+    // The files being renamed (with their canonical path in lowercase), which aren't in the way of another file's new name
+    // since they're moved out of it. Copying leaves them where they are though.
+    let sources: HashSet<String> = if options.copy {
+        HashSet::new()
+    } else {
+        renamable
+            .iter()
+            .flat_map(|(path, .., videos)| {
+                iter::once(path).chain(videos.iter().map(|(path, _, _)| path))
+            })
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .map(|path| path.to_string_lossy().to_lowercase())
+            .collect()
+    };
+    // SHA-256 of every file hashed so far, so comparing against the same file again doesn't read it twice
+    let mut hashes = HashMap::new();
+    // Read-only folders which were already warned about, as they'd otherwise be warned about for every file in them
+    let mut read_only = HashSet::new();
+
+    for (
+        position,
+        (path, path_str, extension, mut timestamp, mut stem, source, raw, datetime, videos),
+    ) in renamable.into_iter().enumerate()
+    {
+        let directory = destination_directory(&path, paths, datetime, options);
+
+        // The photo and its companions (e.g. "IMG_0001.HEIC" and "IMG_0001.MOV") each get that name with their own extension
+        let extensions: Vec<&Option<String>> = iter::once(&extension)
+            .chain(videos.iter().map(|(_, extension, _)| extension))
+            .collect();
+        let group: Vec<&PathBuf> = iter::once(&path)
+            .chain(videos.iter().map(|(path, _, _)| path))
+            .collect();
+        let find_taken = |stem: &str| {
+            find_taken(
+                &claimed,
+                &sources,
+                &directory,
+                stem,
+                &extensions,
+                &group,
+                options,
+            )
+        };
+
+        // The same photo saved twice under different names obviously shares its timestamp, that's not a real conflict.
+        if options.dedupe_content {
+            if let Some(original) = find_duplicate(
+                &claimed,
+                &directory,
+                &stem,
+                &extension,
+                &path,
+                options,
+                &mut hashes,
+            ) {
+                info!(
+                    "Skipping \"{path_str}\", it's identical to \"{}\".",
+                    original.display()
+                );
+                plan.summary.duplicates += 1 + videos.len();
+                continue;
+            }
+        }
+
+        // Two files can't get the same name, "--collision-policy" decides what happens to the later one.
+        // The same goes for files already there that aren't renamed (e.g. an unsupported "2023-05-25_19-47-30.txt").
+        // Files with the same timestamp but different extensions (e.g. a photo and a video) are fine.
+        if let Some((name, owner, on_disk)) = find_taken(&stem) {
+            match options.collision_policy {
+                // Probe for the next free "<timestamp> (n)", or whatever "--dup-format" makes of it
+                CollisionPolicy::Suffix => {
+                    let mut counter = 1;
+
+                    while find_taken(&format!(
+                        "{stem}{}",
+                        format_dup_suffix(&options.dup_format, counter)
+                    ))
+                    .is_some()
+                    {
+                        counter += 1;
+                    }
+
+                    let suffix = format_dup_suffix(&options.dup_format, counter);
+                    warning!(
+                        "duplicate_timestamp",
+                        Some(&path),
+                        "The timestamp ({timestamp}) of \"{path_str}\" already exists, adding suffix \"{}\"...",
+                        suffix.trim()
+                    );
+                    plan.summary.warnings += 1;
+                    timestamp = format!("{timestamp}{suffix}");
+                    stem = format!("{stem}{suffix}");
+                }
+                CollisionPolicy::Skip => {
+                    warning!(
+                        "name_taken",
+                        Some(&path),
+                        "Skipping \"{path_str}\", its new name \"{}\" is already taken by: \"{}\"",
+                        directory.join(&name).display(),
+                        owner.display()
+                    );
+                    plan.summary.warnings += 1;
+                    continue;
+                }
+                CollisionPolicy::Overwrite if on_disk => {
+                    warning!(
+                        "will_overwrite",
+                        Some(&path),
+                        "\"{}\" already exists and will be overwritten by \"{path_str}\"",
+                        owner.display()
+                    );
+                    plan.needs_confirmation = true;
+                    plan.summary.warnings += 1;
+                }
+                CollisionPolicy::Error if on_disk => {
+                    error!(
+                        "destination_exists",
+                        Some(&path),
+                        "Attempted to add \"{path_str}\"\n\t...but its new name is already taken by a file that isn't being renamed: \"{}\"",
+                        owner.display()
+                    );
+                    plan.has_conflicts = true;
+                    plan.summary.conflicts += 1;
+                    continue;
+                }
+                // Overwriting the other file would lose it before it even got its new name,
+                // so that's a conflict just the same. Continue the loop to show all occurrences.
+                CollisionPolicy::Error | CollisionPolicy::Overwrite => {
+                    // "--simulate-conflicts" and "--keep-going" list them all together afterwards instead
+                    if !options.simulate_conflicts && !options.keep_going {
+                        error!(
+                            "name_taken",
+                            Some(&path),
+                            "Attempted to add \"{path_str}\"\n\t...but its new name \"{}\" is already taken by: \"{}\"",
+                            directory.join(&name).display(),
+                            owner.display()
+                        );
+                    }
+
+                    plan.conflict_groups
+                        .entry((directory, name))
+                        .or_insert_with(|| vec![owner])
+                        .push(path);
+                    plan.has_conflicts = true;
+                    plan.summary.conflicts += 1;
+                    continue;
+                }
+            }
+        }
+
+        let mut file = FileInfo {
+            new_path: directory.join(file_name(&stem, &extension, options)),
+            timestamp: timestamp.clone(),
+            path,
+            source,
+            raw: raw.clone(),
+            status: Status::Planned,
+            position,
+            companions: Vec::new(),
+        };
+
+        for (path, extension, source) in videos {
+            file.companions.push(FileInfo {
+                new_path: directory.join(file_name(&stem, &extension, options)),
+                timestamp: timestamp.clone(),
+                path,
+                source,
+                raw: raw.clone(),
+                status: Status::Planned,
+                position,
+                companions: Vec::new(),
+            });
+        }
+
+        check_already_named(&mut file, &mut plan.summary);
+
+        for companion in &mut file.companions {
+            check_already_named(companion, &mut plan.summary);
+        }
+
+        // Which metadata won, e.g. "IMG_0001.HEIC" -> "2023-05-14_21-34-06.heic" [source: EXIF DateTimeOriginal]
+        for info in file
+            .with_companions()
+            .filter(|info| !info.is_already_named())
+        {
+            verbose!(
+                "\"{}\" -> \"{}\" [source: {}]",
+                info.path.display(),
+                info.new_path.display(),
+                info.source
+            );
+        }
+
+        // Better to find out about permission problems now than halfway through renaming
+        for info in file.with_companions() {
+            if !check_writable(info, options, &mut read_only) {
+                plan.needs_confirmation = true;
+                plan.summary.warnings += 1;
+            }
+
+            // Renaming works regardless, but many programs (e.g. Explorer) can't open the file afterwards
+            if !info.is_already_named() && is_too_long(&info.new_path) {
+                warning!(
+                    "path_too_long",
+                    Some(&info.path),
+                    "The new path of \"{}\" is longer than {MAX_PATH} characters, which many Windows programs can't open: \"{}\"",
+                    info.path.display(),
+                    info.new_path.display()
+                );
+                plan.needs_confirmation = true;
+                plan.summary.warnings += 1;
+            }
+        }
+
+        // Already named files stay in the plan too, so no other file can claim their name.
+        for info in file.with_companions() {
+            claimed.insert(
+                (directory.clone(), name_key(&info.new_path)),
+                info.path.clone(),
+            );
+        }
+
+        plan.entries
+            .insert((directory.clone(), name_key(&file.new_path)), file);
+    }
+
+    Ok(plan)
+}
+
+// The "BurstUUID" that Apple gives every photo of a burst, or None if the file isn't part of one
+fn read_burst_uuid(reader: &dyn TagReader, path: &Path) -> Option<String> {
+    let output = reader.read_tag("BurstUUID", path).ok()?;
+    let uuid = String::from_utf8_lossy(&output).trim().to_string();
+
+    Some(uuid).filter(|uuid| !uuid.is_empty())
+}
+
+// Looks for a file in the plan with the same name (or a suffixed one from "--dedupe") and identical contents
+// Returns the path of that file if there is one
+fn find_duplicate(
+    claimed: &HashMap<(PathBuf, String), PathBuf>,
+    directory: &Path,
+    stem: &str,
+    extension: &Option<String>,
+    path: &Path,
+    options: &Options,
+    hashes: &mut HashMap<PathBuf, Option<[u8; 32]>>,
+) -> Option<PathBuf> {
+    let candidates = iter::once(stem.to_string()).chain(
+        (1..)
+            .map(|counter| format!("{stem}{}", format_dup_suffix(&options.dup_format, counter)))
+            .take_while(|_| options.collision_policy == CollisionPolicy::Suffix),
+    );
+
+    for candidate in candidates {
+        let name = file_name(&candidate, extension, options).to_lowercase();
+        let Some(other) = claimed.get(&(directory.to_path_buf(), name)) else {
+            break;
+        };
+
+        if is_same_content(path, other, hashes) {
+            return Some(other.clone());
+        }
+    }
+
+    None
+}
+
+// The name a file would get is taken if any of the names it and its companions would get was claimed by another file.
+// Returns that name along with the original path of the file which claimed it.
+fn find_claimed<'a>(
+    claimed: &'a HashMap<(PathBuf, String), PathBuf>,
+    directory: &Path,
+    stem: &str,
+    extensions: &[&Option<String>],
+    options: &Options,
+) -> Option<(String, &'a PathBuf)> {
+    extensions.iter().find_map(|extension| {
+        let name = file_name(stem, extension, options).to_lowercase();
+        let owner = claimed.get(&(directory.to_path_buf(), name.clone()))?;
+        Some((name, owner))
+    })
+}
+
+// Like "find_claimed", but also finds the files already there that aren't being renamed, which would be overwritten.
+// "group" is the original path of the file and each of its companions, in the order of "extensions".
+// Returns (new file name in lowercase, the file that has it, whether that one isn't in the plan) if the name is taken
+fn find_taken(
+    claimed: &HashMap<(PathBuf, String), PathBuf>,
+    sources: &HashSet<String>,
+    directory: &Path,
+    stem: &str,
+    extensions: &[&Option<String>],
+    group: &[&PathBuf],
+    options: &Options,
+) -> Option<(String, PathBuf, bool)> {
+    if let Some((name, owner)) = find_claimed(claimed, directory, stem, extensions, options) {
+        return Some((name, owner.clone(), false));
+    }
+
+    extensions.iter().zip(group).find_map(|(extension, path)| {
+        let destination = directory.join(file_name(stem, extension, options));
+        let is_source = fs::canonicalize(&destination)
+            .is_ok_and(|file| sources.contains(&file.to_string_lossy().to_lowercase()));
+
+        (is_destination_taken(path, &destination) && !is_source)
+            .then(|| (name_key(&destination), destination, true))
+    })
+}
+
+// New names are compared in lowercase, as names only differing in case
+// would collide on case-insensitive filesystems (the default on Windows and macOS)
+fn name_key(new_path: &Path) -> String {
+    new_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Compares the sizes first, which already tells most files apart without reading them
+// Files that can't be read are never considered identical.
+fn is_same_content(a: &Path, b: &Path, hashes: &mut HashMap<PathBuf, Option<[u8; 32]>>) -> bool {
+    let (Ok(a_metadata), Ok(b_metadata)) = (fs::metadata(a), fs::metadata(b)) else {
+        return false;
+    };
+
+    if a_metadata.len() != b_metadata.len() {
+        return false;
+    }
+
+    let mut hash = |path: &Path| {
+        *hashes
+            .entry(path.to_path_buf())
+            .or_insert_with(|| hash_file(path).ok())
+    };
+
+    match (hash(a), hash(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+// Marks the file as already named if it is, so it's skipped
+// Re-running on an already processed folder shouldn't do anything.
+fn check_already_named(info: &mut FileInfo, summary: &mut Summary) {
+    if info.is_already_named() {
+        info!(
+            "Skipping \"{}\", it's already named after its timestamp.",
+            info.path.display()
+        );
+        summary.already_named += 1;
+        info.status = Status::AlreadyNamed;
+    }
+}
+
+// Warns if the file can't be renamed (or copied) because it or one of the folders involved is read-only
+// Every folder is only warned about once, "read_only" keeps track of those.
+// Returns false if anything was warned about
+fn check_writable(info: &FileInfo, options: &Options, read_only: &mut HashSet<PathBuf>) -> bool {
+    if info.is_already_named() {
+        return true;
+    }
+
+    // Renaming needs write access to the file's folder, while only the destination folder matters for copies.
+    // Folders that don't exist yet (e.g. the output directory) are created in the closest one that does.
+    let mut directories = Vec::new();
+
+    if !options.copy {
+        directories.extend(info.path.parent());
+    }
+    directories.extend(
+        info.new_path
+            .ancestors()
+            .skip(1)
+            .find(|directory| directory.exists()),
+    );
+
+    let mut writable = true;
+
+    for directory in directories {
+        // An empty parent is the current working directory
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+        let is_read_only =
+            fs::metadata(directory).is_ok_and(|metadata| metadata.permissions().readonly());
+
+        if is_read_only && read_only.insert(directory.to_path_buf()) {
+            warning!(
+                "read_only_folder",
+                Some(&info.path),
+                "The folder \"{}\" is read-only, so files in it can't be renamed. Make it writable first, e.g. with \"chmod u+w\".",
+                directory.display()
+            );
+            writable = false;
+        }
+    }
+
+    // Windows doesn't allow renaming read-only files either, regardless of the folder
+    if cfg!(windows)
+        && !options.copy
+        && fs::metadata(&info.path).is_ok_and(|metadata| metadata.permissions().readonly())
+    {
+        warning!(
+            "read_only_file",
+            Some(&info.path),
+            "\"{}\" is read-only, so it can't be renamed. Clear its read-only attribute first, e.g. with \"attrib -r\".",
+            info.path.display()
+        );
+        writable = false;
+    }
+
+    writable
+}
+
+// Windows limits paths to 260 characters (MAX_PATH) unless programs opt into longer ones
+const MAX_PATH: usize = 260;
+
+// Whether the path is too long for programs that don't support long paths on Windows, it's never too long elsewhere
+fn is_too_long(path: &Path) -> bool {
+    cfg!(windows) && path.as_os_str().to_string_lossy().encode_utf16().count() >= MAX_PATH
+}
+
+// Adds what to do about it to errors which are otherwise cryptic, such as "Permission denied"
+fn describe_error(error: &io::Error, info: &FileInfo) -> String {
+    if is_too_long(&info.path) || is_too_long(&info.new_path) {
+        return format!("{error}. The path is longer than {MAX_PATH} characters, try a shorter folder (or enable long paths in Windows).");
+    }
+
+    if error.kind() != io::ErrorKind::PermissionDenied {
+        return error.to_string();
+    }
+
+    if cfg!(windows) {
+        format!("{error}. Make sure the file isn't read-only (\"attrib -r\") or open in another program.")
+    } else {
+        format!("{error}. Make sure you're allowed to write to the file and its folder, e.g. with \"chmod u+w\".")
+    }
+}
+
+// Files are renamed within their own directory unless an output directory (or "--flat") is given,
+// followed by the date subfolders if any, e.g. "2023/05" for "--by-month"
+fn destination_directory(
+    path: &Path,
+    paths: &[PathBuf],
+    datetime: NaiveDateTime,
+    options: &Options,
+) -> PathBuf {
+    let root = match options.output_dir {
+        Some(ref output_dir) => output_dir.clone(),
+        // "--flat" pulls files found in subfolders up into the folder that was given, the innermost one if they're nested
+        None if options.flat => paths
+            .iter()
+            .filter(|root| path.starts_with(root) && path != *root)
+            .max_by_key(|root| root.components().count())
+            .map(PathBuf::as_path)
+            .or(path.parent())
+            .map(PathBuf::from)
+            .unwrap_or_default(),
+        None => path.parent().map(PathBuf::from).unwrap_or_default(),
+    };
+    let Some(date_folders) = options.date_folders else {
+        return root;
+    };
+    let folders = date_folders.path(datetime);
+
+    // Re-running on a folder that was already sorted shouldn't nest the date folders again
+    if options.output_dir.is_none() && root.ends_with(&folders) {
+        return root;
+    }
+
+    root.join(folders)
+}
+
+// Applies the function to every item using up to "jobs" threads, returning the results in the original order.
+// An item the function panicked on gets the panic's message instead, the other items are still handled.
+fn run_in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    function: impl Fn(&T) -> R + Sync,
+) -> Vec<Result<R, String>> {
+    let run = |item: &T| {
+        panic::catch_unwind(AssertUnwindSafe(|| function(item)))
+            .map_err(|payload| panic_message(&*payload))
+    };
+
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(run).collect();
+    }
+
+    // Each thread takes the next unclaimed item until there are none left
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<R, String>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+
+                        results.push((index, run(item)));
+                    }
+
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            // Panics are caught per item, so there's nothing left to handle here
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|payload| panic::resume_unwind(payload))
+            })
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// The message given to "panic!" (or "expect"), which is either a literal or formatted
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// Renames (or copies) every file in the plan, reporting each outcome as it goes
+// Returns the summary of the plan including the renames, or an error without touching anything if the output directory can't be created
+// The status of every file is updated along the way
+pub fn apply_plan(plan: &mut RenamePlan, options: &Options) -> Result<Summary, String> {
+    let mut summary = plan.summary.clone();
+
+    if let Some(ref output_dir) = options.output_dir {
+        if let Err(error) = fs::create_dir_all(output_dir) {
+            return Err(format!(
+                "Error: Failed to create the output directory \"{}\" - {error}",
+                output_dir.display()
+            ));
+        }
+    }
+
+    // The date folders only exist once something is moved into them
+    if options.date_folders.is_some() {
+        let directories: BTreeSet<&Path> = plan
+            .entries
+            .values()
+            .flat_map(FileInfo::with_companions)
+            .filter(|info| info.is_pending())
+            .filter_map(|info| info.new_path.parent())
+            .collect();
+
+        for directory in directories {
+            if let Err(error) = fs::create_dir_all(directory) {
+                return Err(format!(
+                    "Error: Failed to create the date folder \"{}\" - {error}",
+                    directory.display()
+                ));
+            }
+        }
+    }
+
+    // A file can only be renamed to a name another file still has once that one moved out of the way,
+    // e.g. when two files want each other's names. Those are first moved to a temporary name and renamed last.
+    // Map<original path, where it currently is>, which is the original path again if it couldn't be staged.
+    let mut staged = HashMap::new();
+
+    if !options.copy {
+        let sources: HashSet<&PathBuf> = plan
+            .entries
+            .values()
+            .flat_map(FileInfo::with_companions)
+            .filter(|info| info.is_pending())
+            .map(|info| &info.path)
+            .collect();
+
+        for info in plan.files().into_iter().flat_map(FileInfo::with_companions) {
+            if !info.is_pending() || !sources.contains(&info.new_path) {
+                continue;
+            }
+
+            let temporary = staging_path(&info.new_path, staged.len());
+
+            match move_file(&info.path, &temporary) {
+                Ok(()) => {
+                    verbose!(
+                        "Moving \"{}\" to \"{}\" until its new name is free",
+                        info.path.display(),
+                        temporary.display()
+                    );
+                    staged.insert(info.path.clone(), temporary);
+                }
+                Err(error) => {
+                    warning!(
+                        "staging_failed",
+                        Some(&info.path),
+                        "Failed to move \"{}\" out of the way - {error}",
+                        info.path.display()
+                    );
+                    summary.warnings += 1;
+                    staged.insert(info.path.clone(), info.path.clone());
+                }
+            }
+        }
+    }
+
+    // "--collision-policy overwrite" only replaces files that aren't in the plan themselves,
+    // e.g. not one that's still there because it couldn't be moved out of the way.
+    let in_plan: HashSet<PathBuf> = plan
+        .entries
+        .values()
+        .flat_map(FileInfo::with_companions)
+        .map(|info| info.path.clone())
+        .collect();
+    let may_overwrite = |info: &FileInfo| {
+        options.collision_policy == CollisionPolicy::Overwrite && !in_plan.contains(&info.new_path)
+    };
+
+    // Everything that doesn't have to wait goes first, which frees up the names the staged files want
+    'apply: for staged_pass in [false, true] {
+        for info in plan.files_mut() {
+            if staged.contains_key(&info.path) == staged_pass {
+                let overwrite = may_overwrite(info);
+                apply_file(
+                    info,
+                    staged.get(&info.path),
+                    overwrite,
+                    options,
+                    &mut summary,
+                );
+            }
+
+            for companion in &mut info.companions {
+                if staged.contains_key(&companion.path) == staged_pass {
+                    let overwrite = may_overwrite(companion);
+                    apply_file(
+                        companion,
+                        staged.get(&companion.path),
+                        overwrite,
+                        options,
+                        &mut summary,
+                    );
+                }
+            }
+
+            // All or nothing, so there's no point in going on
+            if options.transactional && summary.failed > 0 {
+                break 'apply;
+            }
+        }
+    }
+
+    if options.transactional && summary.failed > 0 {
+        roll_back(plan, &staged, options, &mut summary);
+    }
+
+    Ok(summary)
+}
+
+// Removes the folders that were left empty by moving files out of them (e.g. with "--output-dir"), deepest first
+// Only the folders inside the given ones are removed, never those themselves, and only if nothing at all is left in them.
+pub fn prune_empty_directories(plan: &RenamePlan, paths: &[PathBuf], summary: &mut Summary) {
+    let directories: BTreeSet<&Path> = plan
+        .files()
+        .into_iter()
+        .flat_map(FileInfo::with_companions)
+        .filter(|info| info.status == Status::Renamed)
+        .filter(|info| info.path.parent() != info.new_path.parent())
+        .flat_map(|info| {
+            let root = paths
+                .iter()
+                .filter(|root| info.path.starts_with(root) && info.path != **root)
+                .max_by_key(|root| root.components().count());
+
+            info.path
+                .ancestors()
+                .skip(1)
+                .take_while(move |directory| root.is_some_and(|root| directory != root))
+        })
+        .collect();
+    let mut directories: Vec<&Path> = directories.into_iter().collect();
+    directories.sort_by_key(|directory| Reverse(directory.components().count()));
+
+    for directory in directories {
+        let is_empty = fs::read_dir(directory).is_ok_and(|mut entries| entries.next().is_none());
+
+        if !is_empty {
+            continue;
+        }
+
+        match fs::remove_dir(directory) {
+            Ok(()) => {
+                verbose!("Removed the empty folder \"{}\"", directory.display());
+                summary.pruned += 1;
+            }
+            Err(error) => {
+                warning!(
+                    "prune_failed",
+                    Some(directory),
+                    "Failed to remove the empty folder \"{}\" - {error}",
+                    directory.display()
+                );
+                summary.warnings += 1;
+            }
+        }
+    }
+}
+
+// Undoes every rename (and copy) "apply_plan" did for "--transactional", once one of them failed
+// The staged files go back to their temporary names first, as the other files may want their names back.
+// Only then do the staged files get their original names back, including the ones that weren't renamed yet.
+fn roll_back(
+    plan: &mut RenamePlan,
+    staged: &HashMap<PathBuf, PathBuf>,
+    options: &Options,
+    summary: &mut Summary,
+) {
+    info!("Rolling back the files renamed so far, since \"--transactional\" was passed...");
+    let temporary = |path: &PathBuf| staged.get(path).filter(|&from| from != path).cloned();
+
+    for staged_pass in [true, false] {
+        for_each_file(plan, |info| {
+            let temporary = temporary(&info.path);
+
+            match info.status {
+                Status::Copied if !staged_pass => undo_file(info, None, options, summary),
+                Status::Renamed if temporary.is_some() == staged_pass => {
+                    let target = temporary.unwrap_or_else(|| info.path.clone());
+                    undo_file(info, Some(&target), options, summary);
+                }
+                _ => {}
+            }
+        });
+    }
+
+    for_each_file(plan, |info| {
+        let Some(from) = temporary(&info.path) else {
+            return;
+        };
+
+        // The ones that failed were already moved back (or reported) by "apply_file"
+        if !matches!(info.status, Status::Planned | Status::RolledBack) {
+            return;
+        }
+
+        if info.path.exists() || move_file(&from, &info.path).is_err() {
+            error!(
+                "left_staged",
+                Some(&info.path),
+                "\"{}\" was left at \"{}\"",
+                info.path.display(),
+                from.display()
+            );
+        }
+    });
+}
+
+// Moves a renamed file back to "target" (or removes a copy if there's none), along with its sidecar
+fn undo_file(
+    info: &mut FileInfo,
+    target: Option<&PathBuf>,
+    options: &Options,
+    summary: &mut Summary,
+) {
+    let result = match target {
+        Some(target) if target.exists() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("\"{}\" already exists", target.display()),
+        )),
+        Some(target) => move_file(&info.new_path, target),
+        None => fs::remove_file(&info.new_path),
+    };
+
+    if let Err(error) = result {
+        error!(
+            "rollback_failed",
+            Some(&info.path),
+            "Rolling back \"{}\" failed, it was left at \"{}\" - {error}",
+            info.path.display(),
+            info.new_path.display()
+        );
+        return;
+    }
+
+    if options.sidecar {
+        // It's only extra information, so it's fine if it's gone already
+        let _ = fs::remove_file(sidecar_path(&info.new_path));
+    }
+
+    if info.status == Status::Copied {
+        summary.copied -= 1;
+    } else {
+        summary.renamed -= 1;
+    }
+
+    summary.rolled_back += 1;
+    info.status = Status::RolledBack;
+    verbose!(
+        "Rolled back \"{}\" (it was \"{}\")",
+        info.path.display(),
+        info.new_path.display()
+    );
+}
+
+// Calls "f" for every file in the plan and their companions, in the "--sort" order
+fn for_each_file(plan: &mut RenamePlan, mut f: impl FnMut(&mut FileInfo)) {
+    for info in plan.files_mut() {
+        f(info);
+        info.companions.iter_mut().for_each(&mut f);
+    }
+}
+
+// A hidden name next to the destination, so that the final rename stays on the same filesystem
+fn staging_path(destination: &Path, index: usize) -> PathBuf {
+    destination.with_file_name(format!(".ios2exif-{}-{index}.tmp", process::id()))
+}
+
+// Whether renaming would overwrite another file, which "fs::rename" silently does on Unix.
+// Changing only the case of a name is fine though, even on case-insensitive filesystems where the "other" file is the same one.
+fn is_destination_taken(path: &Path, destination: &Path) -> bool {
+    if !destination.exists() {
+        return false;
+    }
+
+    let same_name = match (path.file_name(), destination.file_name()) {
+        (Some(name), Some(destination_name)) => name
+            .to_string_lossy()
+            .eq_ignore_ascii_case(&destination_name.to_string_lossy()),
+        _ => false,
+    };
+
+    !(same_name && path.parent() == destination.parent())
+}
+
+// Renames (or copies) a single file and updates its status
+// "staged" is where the file was moved to temporarily (if it was), "overwrite" whether a file already at the destination is replaced
+fn apply_file(
+    info: &mut FileInfo,
+    staged: Option<&PathBuf>,
+    overwrite: bool,
+    options: &Options,
+    summary: &mut Summary,
+) {
+    if !info.is_pending() {
+        return;
+    }
+
+    if overwrite && is_destination_taken(&info.path, &info.new_path) {
+        info!(
+            "Overwriting \"{}\" with \"{}\"...",
+            info.new_path.display(),
+            info.path.display()
+        );
+    }
+
+    if options.copy {
+        // Check again in case the destination was created after planning.
+        let result = if info.new_path.exists() && !overwrite {
+            Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        } else {
+            fs::copy(&info.path, &info.new_path)
+        };
+
+        if let Err(error) = result {
+            error!(
+                "copy_failed",
+                Some(&info.path),
+                "Copying failed for \"{}\" - {}",
+                info.path.display(),
+                describe_error(&error, info)
+            );
+            summary.failed += 1;
+            info.status = Status::Failed(error.to_string());
+        } else {
+            summary.copied += 1;
+            info.status = Status::Copied;
+            info!(
+                "Copying success for \"{}\" to timestamp \"{}\".",
+                info.path.display(),
+                info.timestamp
+            );
+            apply_sidecar(info, options, summary);
+        }
+
+        return;
+    }
+
+    let from = staged.unwrap_or(&info.path);
+    let result = if is_destination_taken(&info.path, &info.new_path) && !overwrite {
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("\"{}\" already exists", info.new_path.display()),
+        ))
+    } else {
+        move_file(from, &info.new_path)
+    };
+
+    if let Err(error) = result {
+        error!(
+            "rename_failed",
+            Some(&info.path),
+            "Renaming failed for \"{}\" - {}",
+            info.path.display(),
+            describe_error(&error, info)
+        );
+
+        // Give a staged file its original name back, which is free unless something else took it in the meantime
+        if *from != info.path && (info.path.exists() || move_file(from, &info.path).is_err()) {
+            error!(
+                "left_staged",
+                Some(&info.path),
+                "\"{}\" was left at \"{}\"",
+                info.path.display(),
+                from.display()
+            );
+        }
+
+        summary.failed += 1;
+        info.status = Status::Failed(error.to_string());
+    } else {
+        summary.renamed += 1;
+        info.status = Status::Renamed;
+        info!(
+            "Renaming success for \"{}\" to timestamp \"{}\".",
+            info.path.display(),
+            info.timestamp
+        );
+        apply_sidecar(info, options, summary);
+    }
+}
+
+// Expands the given paths into a flat list of files.
+// Directories are scanned (including subdirectories up to "max_depth" levels below them, 0 being none) while files are added directly.
+// Symbolic links found in directories are skipped unless "follow_symlinks" is set, the given paths are always followed.
+// Returns an error if one of the given paths can't be used at all, "warnings" counts the ones that can partially be used.
+pub fn collect_files(
+    paths: &[PathBuf],
+    max_depth: usize,
+    include_hidden: bool,
+    follow_symlinks: bool,
+    warnings: &mut usize,
+) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    // Links can point back up the tree, so every directory is only scanned once
+    let mut scanned = HashSet::new();
+
+    for path in paths {
+        if path.is_file() {
+            files.push(path.clone());
+            continue;
+        }
+
+        if !path.is_dir() {
+            return Err(format!(
+                "Error: \"{}\" either doesn't exist or isn't a valid directory.",
+                path.display()
+            ));
+        }
+
+        // Walk the directory tree manually with a stack of directories left to scan, along with how deep they are
+        let mut directories = vec![(path.clone(), 0)];
+
+        while let Some((directory, depth)) = directories.pop() {
+            if !scanned.insert(fs::canonicalize(&directory).unwrap_or_else(|_| directory.clone())) {
+                verbose!(
+                    "Skipping \"{}\", it was already scanned",
+                    directory.display()
+                );
+                continue;
+            }
+
+            let Ok(entries) = fs::read_dir(&directory) else {
+                warning!(
+                    "unreadable_directory",
+                    Some(&directory),
+                    "The directory \"{}\" can't be read.",
+                    directory.display()
+                );
+                *warnings += 1;
+                continue;
+            };
+
+            for entry in entries {
+                // Ignore the file if it can't be read
+                let Ok(entry) = entry else {
+                    warning!("unreadable_file", None, "A file can't be read.");
+                    *warnings += 1;
+                    continue;
+                };
+                let path = entry.path();
+
+                // Files given directly are always used, but the clutter found inside folders isn't
+                if is_apple_double(&path) || (!include_hidden && is_hidden(&path)) {
+                    verbose!("Skipping hidden \"{}\"", path.display());
+                    continue;
+                }
+
+                // Renaming a link doesn't rename what it points to, which may not even be in the same folder
+                let is_symlink = entry
+                    .file_type()
+                    .is_ok_and(|file_type| file_type.is_symlink());
+
+                if is_symlink && !follow_symlinks {
+                    verbose!("Skipping symbolic link \"{}\"", path.display());
+                    continue;
+                }
+
+                // A link to something that doesn't exist (anymore) can't be read
+                if is_symlink && !path.exists() {
+                    warning!(
+                        "broken_symlink",
+                        Some(&path),
+                        "The symbolic link \"{}\" points to something that doesn't exist, ignoring...",
+                        path.display()
+                    );
+                    *warnings += 1;
+                    continue;
+                }
+
+                // Ignore directories unless they have to be scanned as well
+                if path.is_dir() {
+                    if depth < max_depth {
+                        directories.push((path, depth + 1));
+                    } else if max_depth > 0 {
+                        verbose!(
+                            "Skipping \"{}\", it's deeper than \"--max-depth\"",
+                            path.display()
+                        );
+                    }
+                    continue;
+                }
+
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+// The sidecar files written by "--sidecar", e.g. "2023-05-25_19-47-30.heic.ios2exif.json"
+const SIDECAR_SUFFIX: &str = ".ios2exif.json";
+
+// Google Takeout has a JSON file for each file (e.g. "IMG_1234.jpg.json") along with one for each album ("metadata.json")
+fn is_takeout_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn is_sidecar(path: &Path) -> bool {
+    sidecar_owner(path).is_some()
+}
+
+// The file a sidecar file belongs to, i.e. its path without SIDECAR_SUFFIX
+// Goes by the extensions rather than the (lossy) string, so names that aren't valid UTF-8 keep their bytes.
+fn sidecar_owner(path: &Path) -> Option<PathBuf> {
+    let stem = Path::new(path.file_stem()?);
+
+    if path.extension()? != "json" || stem.extension()? != "ios2exif" {
+        return None;
+    }
+
+    Some(path.with_file_name(stem.file_stem()?))
+}
+
+// The file itself was renamed already, so a sidecar that can't be written is only a warning
+fn apply_sidecar(info: &FileInfo, options: &Options, summary: &mut Summary) {
+    if !options.sidecar {
+        return;
+    }
+
+    // JSON can only hold valid UTF-8, so "undo" would rename the file to some other path
+    if info.path.to_str().is_none() {
+        warning!(
+            "sidecar_failed",
+            Some(&info.path),
+            "Not writing a sidecar file for \"{}\", its original path isn't valid UTF-8.",
+            info.new_path.display()
+        );
+        summary.warnings += 1;
+        return;
+    }
+
+    if let Err(error) = write_sidecar(info) {
+        warning!(
+            "sidecar_failed",
+            Some(&info.path),
+            "Failed to write the sidecar file for \"{}\" - {error}",
+            info.new_path.display()
+        );
+        summary.warnings += 1;
+    }
+}
+
+// E.g. "2023-05-25_19-47-30.heic.ios2exif.json" for "2023-05-25_19-47-30.heic"
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+// Records where the timestamp of a renamed (or copied) file came from next to it
+fn write_sidecar(info: &FileInfo) -> io::Result<()> {
+    let sidecar = json!({
+        "original": info.path.file_name().map(|name| name.to_string_lossy()),
+        "original_path": info.path.to_string_lossy(),
+        "timestamp": info.timestamp,
+        "source": info.source,
+        "raw": info.raw,
+    });
+
+    fs::write(sidecar_path(&info.new_path), format!("{sidecar:#}\n"))
+}
+
+// Renames the files found in "paths" back to the original paths their sidecar files (from "--sidecar") recorded,
+// removing the sidecar files along with it. Files whose original path is taken again (e.g. copies) are left alone.
+// Returns an error if one of the given paths can't be used at all.
+pub fn undo_renames(paths: &[PathBuf], options: &Options) -> Result<Summary, String> {
+    let mut summary = Summary::default();
+    let max_depth = if options.recursive {
+        options.max_depth.unwrap_or(usize::MAX)
+    } else {
+        0
+    };
+    let files = collect_files(
+        paths,
+        max_depth,
+        options.include_hidden,
+        options.follow_symlinks,
+        &mut summary.warnings,
+    )?;
+
+    for (sidecar, path) in files
+        .iter()
+        .filter_map(|sidecar| Some((sidecar, sidecar_owner(sidecar)?)))
+    {
+        let name = sidecar.to_string_lossy();
+        let original = fs::read(sidecar)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<Value>(&contents).ok())
+            .and_then(|sidecar| Some(PathBuf::from(sidecar.get("original_path")?.as_str()?)));
+        summary.processed += 1;
+
+        let Some(original) = original else {
+            warning!(
+                "invalid_sidecar",
+                Some(sidecar),
+                "Skipping \"{name}\", it doesn't record the original path of the file."
+            );
+            summary.warnings += 1;
+            continue;
+        };
+
+        if !path.is_file() {
+            warning!(
+                "missing_file",
+                Some(sidecar),
+                "Skipping \"{name}\", the file it belongs to isn't there anymore."
+            );
+            summary.warnings += 1;
+            continue;
+        }
+
+        if original.symlink_metadata().is_ok() {
+            warning!(
+                "original_taken",
+                Some(&path),
+                "Skipping \"{}\", \"{}\" exists again (or was copied rather than renamed).",
+                path.display(),
+                original.display()
+            );
+            summary.warnings += 1;
+            continue;
+        }
+
+        if options.dry_run {
+            info!(
+                "[dry-run] Would rename \"{}\" back to \"{}\".",
+                path.display(),
+                original.display()
+            );
+            summary.renamed += 1;
+            continue;
+        }
+
+        // The original folder may have been removed by "--prune-empty"
+        let result = original
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| move_file(&path, &original));
+
+        if let Err(error) = result {
+            error!(
+                "rename_failed",
+                Some(&path),
+                "Renaming \"{}\" back to \"{}\" failed - {error}",
+                path.display(),
+                original.display()
+            );
+            summary.failed += 1;
+            continue;
+        }
+
+        info!(
+            "Renamed \"{}\" back to \"{}\".",
+            path.display(),
+            original.display()
+        );
+        summary.renamed += 1;
+
+        if let Err(error) = fs::remove_file(sidecar) {
+            warning!(
+                "sidecar_failed",
+                Some(sidecar),
+                "Failed to remove the sidecar file \"{name}\" - {error}"
+            );
+            summary.warnings += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+// Whether the extension passes "--only" (if given) and "--exclude", both of which are lowercase
+// Files without an extension are only selected if there's no "--only".
+fn is_extension_selected(path: &Path, options: &Options) -> bool {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    if let Some(only) = &options.only {
+        let Some(ref extension) = extension else {
+            return false;
+        };
+
+        if !only.contains(extension) {
+            return false;
+        }
+    }
+
+    match extension {
+        Some(extension) => !options.exclude.contains(&extension),
+        None => true,
+    }
+}
+
+// Whether the file name matches any "--pattern", which every file does if none were given
+fn is_pattern_matched(path: &Path, options: &Options) -> bool {
+    let match_options = MatchOptions {
+        case_sensitive: false,
+        ..MatchOptions::default()
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    options.patterns.is_empty()
+        || options
+            .patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(&name, match_options))
+}
+
+// Whether scanning the folder of the file would use it, which skips the same files that "collect_files" does
+// "--watch" checks the files showing up in the folders with this.
+pub fn is_found_in_folder(path: &Path, include_hidden: bool) -> bool {
+    !is_apple_double(path) && (include_hidden || !is_hidden(path))
+}
+
+// Dotfiles (e.g. ".DS_Store") and the files Windows creates on its own (e.g. "Thumbs.db"),
+// as well as anything with the hidden or system attribute on Windows.
+fn is_hidden(path: &Path) -> bool {
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+
+    if name.starts_with('.')
+        || name.eq_ignore_ascii_case("Thumbs.db")
+        || name.eq_ignore_ascii_case("desktop.ini")
+    {
+        return true;
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            return metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM)
+                != 0;
+        }
+    }
+
+    false
+}
+
+// macOS stores resource forks as "._<name>" next to the actual file on drives that don't support them (e.g. FAT32),
+// so these show up wherever the files were copied. They're never photos or videos even though they share the extension.
+fn is_apple_double(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("._"))
+}
+
+// Renames a file, falling back to copying then removing the original if the destination is on another filesystem
+fn move_file(from: &PathBuf, to: &PathBuf) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+// Joins the new file stem with the extension (if any) in the requested case
+fn file_name(stem: &str, extension: &Option<String>, options: &Options) -> String {
+    match extension {
+        Some(extension) => match options.extension_case {
+            ExtensionCase::Lower => format!("{stem}.{}", extension.to_lowercase()),
+            ExtensionCase::Upper => format!("{stem}.{}", extension.to_uppercase()),
+            ExtensionCase::Preserve => format!("{stem}.{extension}"),
+        },
+        None => stem.to_string(),
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Read};
+
+// Every PNG file starts with these bytes (https://www.w3.org/TR/png/#5PNG-file-signature)
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Text chunks are small, anything bigger than this isn't worth reading into memory
+const MAX_TEXT_CHUNK: u32 = 1024 * 1024;
+
+// The XMP properties which hold the creation time, in order of preference
+// iOS screenshots use "photoshop:DateCreated", which exiftool reports as "DateCreated".
+const XMP_PROPERTIES: [&str; 3] = [
+    "photoshop:DateCreated",
+    "xmp:CreateDate",
+    "exif:DateTimeOriginal",
+];
+
+// Reads the creation time of a PNG file from its text chunks, e.g. "2019-10-15T02:08:48"
+// XMP (in an "iTXt" chunk) is preferred over the "Creation Time" keyword (https://www.w3.org/TR/png/#11keywords).
+// Compressed text isn't supported, exiftool can still be used for those.
+// Returns None if the file has neither.
+pub fn read_png_date(mut reader: impl Read) -> io::Result<Option<String>> {
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature)?;
+
+    if signature != SIGNATURE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a PNG file"));
+    }
+
+    let mut creation_time = None;
+
+    // Each chunk is its length, type, data and a CRC (which is ignored)
+    loop {
+        let mut header = [0; 8];
+
+        match reader.read_exact(&mut header) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = &header[4..];
+
+        if kind == b"IEND" {
+            break;
+        }
+
+        if !matches!(kind, b"tEXt" | b"iTXt") || length > MAX_TEXT_CHUNK {
+            io::copy(
+                &mut reader.by_ref().take(u64::from(length) + 4),
+                &mut io::sink(),
+            )?;
+            continue;
+        }
+
+        let mut data = vec![0; length as usize + 4];
+        reader.read_exact(&mut data)?;
+        data.truncate(length as usize);
+
+        let Some((keyword, text)) = split_text_chunk(kind, &data) else {
+            continue;
+        };
+
+        match keyword {
+            b"XML:com.adobe.xmp" => {
+                let xmp = String::from_utf8_lossy(text);
+
+                if let Some(date) = XMP_PROPERTIES
+                    .iter()
+                    .find_map(|property| find_xmp_property(&xmp, property))
+                {
+                    return Ok(Some(date.to_string()));
+                }
+            }
+            b"Creation Time" if creation_time.is_none() => {
+                // "tEXt" is Latin-1, though dates are plain ASCII anyway
+                creation_time = Some(text.iter().map(|&byte| char::from(byte)).collect());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(creation_time)
+}
+
+// Splits a text chunk into its keyword and (uncompressed) text
+// "tEXt" is "keyword\0text", "iTXt" is "keyword\0" followed by the compression flag and method,
+// then "language\0translated keyword\0text".
+fn split_text_chunk<'a>(kind: &[u8], data: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let separator = data.iter().position(|&byte| byte == 0)?;
+    let (keyword, rest) = (&data[..separator], &data[separator + 1..]);
+
+    if kind == b"tEXt" {
+        return Some((keyword, rest));
+    }
+
+    let [compressed, _method, rest @ ..] = rest else {
+        return None;
+    };
+
+    if *compressed != 0 {
+        return None;
+    }
+
+    let mut fields = rest.splitn(3, |&byte| byte == 0);
+    let (_language, _translated, text) = (fields.next()?, fields.next()?, fields.next()?);
+
+    Some((keyword, text))
+}
+
+// Finds the value of an XMP property, written either as an element or as an attribute:
+// "<photoshop:DateCreated>2019-10-15T02:08:48</photoshop:DateCreated>" or "photoshop:DateCreated="2019-10-15T02:08:48""
+fn find_xmp_property<'a>(xmp: &'a str, property: &str) -> Option<&'a str> {
+    for (start, end) in [
+        (format!("<{property}>"), '<'),
+        (format!("{property}=\""), '"'),
+    ] {
+        if let Some(index) = xmp.find(&start) {
+            let value = xmp[index + start.len()..].split(end).next()?.trim();
+
+            if !value.is_empty() {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
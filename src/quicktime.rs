@@ -0,0 +1,76 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+// QuickTime counts seconds from 1904-01-01 00:00:00 UTC, this many seconds before the Unix epoch
+pub const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+// Reads the creation time from the movie header ("moov" > "mvhd") of a QuickTime or MP4 file,
+// in seconds since 1904 (UTC). This is what exiftool reports as "CreateDate".
+// Returns None if there's no movie header or the creation time was never set (0).
+pub fn read_creation_time(mut reader: impl Read + Seek) -> io::Result<Option<u64>> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let Some(moov_end) = find_atom(&mut reader, end, b"moov")? else {
+        return Ok(None);
+    };
+    let Some(_) = find_atom(&mut reader, moov_end, b"mvhd")? else {
+        return Ok(None);
+    };
+
+    // Version and flags, followed by the creation time which is 64 bits long in version 1
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+
+    let creation_time = if version[0] == 1 {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        u64::from_be_bytes(bytes)
+    } else {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        u64::from(u32::from_be_bytes(bytes))
+    };
+
+    Ok(Some(creation_time).filter(|&seconds| seconds > 0))
+}
+
+// Skips over atoms until one of the given type, stopping at "end" (the end of the parent atom)
+// Returns the end of the atom found with the reader positioned at its contents.
+// Each atom is its 32 bit size (1 if a 64 bit size follows, 0 if it runs until the end), type and contents.
+fn find_atom(reader: &mut (impl Read + Seek), end: u64, kind: &[u8; 4]) -> io::Result<Option<u64>> {
+    loop {
+        let start = reader.stream_position()?;
+
+        if start + 8 > end {
+            return Ok(None);
+        }
+
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => end - start,
+            1 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                u64::from_be_bytes(bytes)
+            }
+            size => u64::from(size),
+        };
+        let atom_end = start.saturating_add(size);
+
+        // A size that's too small (or too big) means the file is broken, so don't go any further
+        if size < 8 || atom_end > end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid atom size",
+            ));
+        }
+
+        if header[4..] == *kind {
+            return Ok(Some(atom_end));
+        }
+
+        reader.seek(SeekFrom::Start(atom_end))?;
+    }
+}
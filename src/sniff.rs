@@ -0,0 +1,52 @@
+use std::{fs::File, io::Read, path::Path};
+
+// The major brands of ISO base media files (the "ftyp" box) that are HEIC photos rather than videos
+const HEIC_BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+];
+
+// The major brands of MP4 videos, anything else (e.g. "M4A " for audio or "crx " for Canon RAW) isn't supported
+const MP4_BRANDS: [&[u8; 4]; 7] = [
+    b"isom", b"iso2", b"mp41", b"mp42", b"avc1", b"dash", b"MSNV",
+];
+
+// Tells the type of file from its first bytes, for files without an extension (e.g. from a messaging app export)
+// Returns the (lowercase) extension it should have, or None if it isn't a supported photo or video.
+pub fn sniff_extension(path: &Path) -> Option<&'static str> {
+    let mut header = [0; 16];
+    let length = File::open(path).ok()?.read(&mut header).ok()?;
+    let header = &header[..length];
+
+    match header {
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("png"),
+        // Canon RAW photos are TIFF files as well, marked right after the header
+        [b'I', b'I', b'*', 0, _, _, _, _, b'C', b'R', ..] => Some("cr2"),
+        [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => Some("tiff"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'A', b'V', b'I', b' ', ..] => Some("avi"),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some("mkv"),
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] => {
+            let brand: &[u8; 4] = brand.get(..4)?.try_into().ok()?;
+
+            if HEIC_BRANDS.contains(&brand) {
+                Some("heic")
+            } else if brand == b"qt  " {
+                Some("mov")
+            } else if brand.starts_with(b"3g") {
+                Some("3gp")
+            } else if brand.starts_with(b"M4V") {
+                Some("m4v")
+            } else if MP4_BRANDS.contains(&brand) {
+                Some("mp4")
+            } else {
+                None
+            }
+        }
+        // Older QuickTime videos don't have an "ftyp" box and start with one of these instead
+        [_, _, _, _, b'm', b'o', b'o', b'v', ..]
+        | [_, _, _, _, b'm', b'd', b'a', b't', ..]
+        | [_, _, _, _, b'w', b'i', b'd', b'e', ..] => Some("mov"),
+        _ => None,
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::{Map, Value};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// When "--since-last-run" last finished successfully in each folder, so the next run only looks at what changed since
+// Map<canonical path of the folder (or file) given, milliseconds since the Unix epoch>
+#[derive(Debug, Default)]
+pub struct LastRuns {
+    path: PathBuf,
+    runs: Map<String, Value>,
+}
+
+// "$XDG_STATE_HOME/ios2exif/last-run.json", falling back to "~/.local/state/ios2exif/last-run.json"
+// On Windows it's "%LOCALAPPDATA%\ios2exif\last-run.json" instead.
+pub fn state_path() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if let Some(directory) = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        PathBuf::from(directory)
+    } else {
+        PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("state")
+    };
+
+    Some(directory.join("ios2exif").join("last-run.json"))
+}
+
+// The key of a folder, which is the same however it's given (e.g. "." or its full path)
+fn key(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+impl LastRuns {
+    // Reads the state file, which doesn't exist before the first run
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let runs = match fs::read(path) {
+            Ok(contents) => match serde_json::from_slice(&contents) {
+                Ok(Value::Object(runs)) => runs,
+                _ => {
+                    return Err(format!(
+                        "Error: The state file \"{}\" of \"--since-last-run\" is corrupt, remove it to start over.",
+                        path.display()
+                    ))
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Map::new(),
+            Err(error) => {
+                return Err(format!(
+                    "Error: Failed to read the state file \"{}\" - {error}",
+                    path.display()
+                ))
+            }
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            runs,
+        })
+    }
+
+    // The earliest of the last runs in these folders, or None if one of them wasn't run in before
+    pub fn since(&self, paths: &[PathBuf]) -> Option<SystemTime> {
+        paths
+            .iter()
+            .map(|path| {
+                let milliseconds = self.runs.get(&key(path))?.as_u64()?;
+                UNIX_EPOCH.checked_add(Duration::from_millis(milliseconds))
+            })
+            // None comes first, so a single folder that wasn't run in before means all files are looked at
+            .min()
+            .flatten()
+    }
+
+    // Records a successful run in these folders, which started at "time" so nothing modified during it is missed
+    pub fn record(&mut self, paths: &[PathBuf], time: SystemTime) {
+        let milliseconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| {
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+        });
+
+        for path in paths {
+            self.runs.insert(key(path), Value::from(milliseconds));
+        }
+    }
+
+    // Writes the state file, creating its folder if needed
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        fs::write(&self.path, Value::Object(self.runs.clone()).to_string())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{options::Options, plan::is_found_in_folder, verbose, warning};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+
+// How long a file has to stay the same before it's renamed, as it's most likely still being written (or synced) otherwise
+pub const SETTLE_TIME: Duration = Duration::from_secs(2);
+// How often the files that are still settling are checked again
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Watches the given folders (and their subfolders with "--recursive") for files being created in or moved into them,
+// calling "on_ready" with every batch of files that stopped changing. Only returns if watching fails.
+pub fn watch_directories(
+    paths: &[PathBuf],
+    options: &Options,
+    mut on_ready: impl FnMut(Vec<PathBuf>),
+) -> Result<(), String> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|error| format!("Error: Failed to start watching for new files - {error}"))?;
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+
+    for path in paths.iter().filter(|path| path.is_dir()) {
+        watcher
+            .watch(path, mode)
+            .map_err(|error| format!("Error: Failed to watch \"{}\" - {error}", path.display()))?;
+    }
+
+    let mut settling: HashMap<PathBuf, Settling> = HashMap::new();
+
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            // Renames count as modifications, which covers files moved in from elsewhere
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event
+                    .paths
+                    .iter()
+                    .filter_map(|path| watched_path(path, paths, options))
+                {
+                    settling.insert(path, Settling::new(Instant::now()));
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(error)) => {
+                warning!(
+                    "watch_failed",
+                    None,
+                    "Something went wrong while watching for new files - {error}"
+                );
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(String::from(
+                    "Error: Stopped receiving new files, as the folders can't be watched anymore.",
+                ));
+            }
+        }
+
+        let mut ready = Vec::new();
+
+        settling.retain(|path, file| {
+            // Gone again, e.g. a temporary file or the old name of a file that was moved
+            let Ok(metadata) = fs::metadata(path) else {
+                return false;
+            };
+
+            if file.has_settled(metadata.len(), metadata.modified().ok(), Instant::now()) {
+                ready.push(path.clone());
+                return false;
+            }
+
+            true
+        });
+
+        if !ready.is_empty() {
+            ready.sort();
+            verbose!("{} new file(s) stopped changing", ready.len());
+            on_ready(ready);
+        }
+    }
+}
+
+// What's known about a file that was created or modified, until it stops changing
+pub struct Settling {
+    size: u64,
+    modified: Option<SystemTime>,
+    // When either of them last changed
+    changed: Instant,
+}
+
+impl Settling {
+    // Nothing is known about the file yet, so the first check always counts as a change
+    pub fn new(now: Instant) -> Self {
+        Self {
+            size: 0,
+            modified: None,
+            changed: now,
+        }
+    }
+
+    // Takes in the current size and modification time of the file,
+    // returns whether it's ready as neither of them changed for "SETTLE_TIME"
+    pub fn has_settled(&mut self, size: u64, modified: Option<SystemTime>, now: Instant) -> bool {
+        if (size, modified) != (self.size, self.modified) {
+            (self.size, self.modified) = (size, modified);
+            self.changed = now;
+            return false;
+        }
+
+        now.duration_since(self.changed) >= SETTLE_TIME
+    }
+}
+
+// The canonical path of the file if scanning the folders would've found it, as only files within "--max-depth" are renamed
+// The folders may be given relatively or through a link, which the paths of the events don't necessarily match.
+pub fn watched_path(path: &Path, paths: &[PathBuf], options: &Options) -> Option<PathBuf> {
+    if !path.is_file() || !is_found_in_folder(path, options.include_hidden) {
+        return None;
+    }
+
+    let path = fs::canonicalize(path).ok()?;
+    let depth = paths
+        .iter()
+        .filter_map(|root| {
+            let relative = path.strip_prefix(fs::canonicalize(root).ok()?).ok()?;
+            Some(relative.components().count().saturating_sub(1))
+        })
+        .min();
+
+    depth
+        .filter(|&depth| options.max_depth.is_none_or(|max_depth| depth <= max_depth))
+        .map(|_| path)
+}
@@ -0,0 +1,125 @@
+use ios2exif::{load_config, Config};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output, Stdio},
+};
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+// Writes the config file (as "ios2exif/config.toml" in the directory, where the program looks for it) and loads it
+fn write_config(directory: &Path, contents: &str) -> Result<Config, String> {
+    let path = directory.join("ios2exif").join("config.toml");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, contents).unwrap();
+    load_config(&path)
+}
+
+// Runs the program on a copy of a JPEG taken at 2023-05-14 21:34:06.123, with the config file in "config"
+// Returns the output and the names of the files afterwards.
+fn run(directory: &Path, config: &Path, arguments: &[&str]) -> (Output, Vec<String>) {
+    let photos = directory.join("photos");
+    let _ = fs::remove_dir_all(&photos);
+    fs::create_dir_all(&photos).unwrap();
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("datetimeoriginal.jpg");
+    fs::copy(fixture, photos.join("IMG_0001.jpg")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ios2exif"))
+        .env("XDG_CONFIG_HOME", config)
+        .env("APPDATA", config)
+        .arg("--no-exiftool")
+        .args(arguments)
+        .arg(&photos)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let mut names: Vec<String> = fs::read_dir(&photos)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+
+    (output, names)
+}
+
+#[test]
+fn values() {
+    let directory = directory("config-values");
+    let config = write_config(
+        &directory,
+        "format = \"%Y%m%d\"\ntz = \"utc\"\nexiftool_path = \"/usr/bin/exiftool\"\ndry_run = true\n",
+    )
+    .unwrap();
+
+    assert_eq!(config.format.as_deref(), Some("%Y%m%d"));
+    assert_eq!(config.timezone.as_deref(), Some("utc"));
+    assert_eq!(
+        config.exiftool_path,
+        Some(PathBuf::from("/usr/bin/exiftool"))
+    );
+    assert_eq!(config.dry_run, Some(true));
+    assert_eq!(config.date_separator, None);
+
+    // Not having one is the same as an empty one
+    let config = load_config(&directory.join("missing.toml")).unwrap();
+    assert_eq!(config.format, None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn invalid() {
+    let directory = directory("config-invalid");
+
+    let error = write_config(&directory, "dryrun = true\n").unwrap_err();
+    assert!(error.contains("Unknown key \"dryrun\""), "{error}");
+    let error = write_config(&directory, "dry_run = \"yes\"\n").unwrap_err();
+    assert!(error.contains("true or false"), "{error}");
+    let error = write_config(&directory, "format = 1\n").unwrap_err();
+    assert!(error.contains("a string"), "{error}");
+    let error = write_config(&directory, "format = \n").unwrap_err();
+    assert!(error.contains("isn't valid TOML"), "{error}");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+// The command line takes precedence over the config file
+#[test]
+fn overridden_by_arguments() {
+    let directory = directory("config-arguments");
+    write_config(&directory, "dry_run = true\nformat = \"%Y%m%d\"\n").unwrap();
+
+    let (output, names) = run(&directory, &directory, &[]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[dry-run] Would rename"));
+    assert_eq!(names, ["IMG_0001.jpg"]);
+
+    let (output, names) = run(&directory, &directory, &["--no-dry-run"]);
+    assert!(output.status.success());
+    assert_eq!(names, ["20230514-123.jpg"]);
+
+    let (_, names) = run(
+        &directory,
+        &directory,
+        &["--no-dry-run", "--format", "%Y-%m-%d"],
+    );
+    assert_eq!(names, ["2023-05-14-123.jpg"]);
+
+    // Anything wrong with it stops the program before doing anything
+    write_config(&directory, "dry_run = false\nformt = \"%Y\"\n").unwrap_err();
+    let (output, names) = run(&directory, &directory, &[]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown key \"formt\""));
+    assert_eq!(names, ["IMG_0001.jpg"]);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
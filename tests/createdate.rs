@@ -0,0 +1,66 @@
+use ios2exif::{get_timestamp_from_exiftool, ExiftoolSession, ExiftoolTags};
+use std::path::Path;
+
+// Reads CreateDate from an already batched result, so exiftool never has to run
+fn createdate(value: &str) -> Option<String> {
+    let tags = ExiftoolTags::from([(String::from("CreateDate"), value.to_string())]);
+    let session = ExiftoolSession::new(Path::new("exiftool-is-not-needed"));
+    let parts = get_timestamp_from_exiftool(
+        Path::new("video.mov"),
+        "video.mov",
+        "CreateDate",
+        false,
+        Some(&tags),
+        &session,
+    )
+    .ok()?;
+
+    // CreateDate never has an offset of its own, it's UTC
+    assert_eq!(parts.offset, None);
+
+    Some(parts.datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+#[test]
+fn createdate_without_offset() {
+    assert_eq!(
+        createdate("2023:05:14 21:34:06"),
+        Some(String::from("2023-05-14 21:34:06"))
+    );
+    assert_eq!(
+        createdate(" 2023:05:14 21:34:06 "),
+        Some(String::from("2023-05-14 21:34:06"))
+    );
+}
+
+#[test]
+fn createdate_with_offset_is_invalid() {
+    assert_eq!(createdate("2023:05:14 21:34:06-05:00"), None);
+    assert_eq!(createdate("2023:05:14 21:34:06Z"), None);
+}
+
+#[test]
+fn createdate_invalid() {
+    assert_eq!(createdate(""), None);
+    assert_eq!(createdate("2023:05:14"), None);
+    assert_eq!(createdate("2023:13:14 21:34:06"), None);
+    assert_eq!(createdate("0000:00:00 00:00:00"), None);
+}
+
+#[test]
+fn missing_tag() {
+    let tags = ExiftoolTags::new();
+    let session = ExiftoolSession::new(Path::new("exiftool-is-not-needed"));
+
+    for (tag, has_timezone) in [("CreateDate", false), ("CreationDate", true)] {
+        assert!(get_timestamp_from_exiftool(
+            Path::new("video.mov"),
+            "video.mov",
+            tag,
+            has_timezone,
+            Some(&tags),
+            &session,
+        )
+        .is_err());
+    }
+}
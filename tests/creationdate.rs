@@ -0,0 +1,85 @@
+use chrono::{FixedOffset, NaiveDate};
+use ios2exif::{
+    get_timestamp_from_exiftool, parse_datetime_with_offset, ExiftoolSession, ExiftoolTags,
+};
+use std::path::Path;
+
+// Reads CreationDate from an already batched result, so exiftool never has to run
+fn creationdate(value: &str) -> Option<(String, Option<FixedOffset>)> {
+    let tags = ExiftoolTags::from([(String::from("CreationDate"), value.to_string())]);
+    let session = ExiftoolSession::new(Path::new("exiftool-is-not-needed"));
+    let parts = get_timestamp_from_exiftool(
+        Path::new("video.mov"),
+        "video.mov",
+        "CreationDate",
+        true,
+        Some(&tags),
+        &session,
+    )
+    .ok()?;
+
+    Some((
+        parts.datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        parts.offset,
+    ))
+}
+
+#[test]
+fn creationdate_with_offset() {
+    assert_eq!(
+        creationdate("2023:05:14 21:34:06-05:00"),
+        Some((
+            String::from("2023-05-14 21:34:06"),
+            FixedOffset::west_opt(5 * 3600)
+        ))
+    );
+    assert_eq!(
+        creationdate("2023:05:14 21:34:06+05:30"),
+        Some((
+            String::from("2023-05-14 21:34:06"),
+            FixedOffset::east_opt(5 * 3600 + 30 * 60)
+        ))
+    );
+}
+
+#[test]
+fn creationdate_without_offset() {
+    assert_eq!(
+        creationdate("2023:05:14 21:34:06"),
+        Some((String::from("2023-05-14 21:34:06"), None))
+    );
+}
+
+#[test]
+fn creationdate_with_utc_designator() {
+    assert_eq!(
+        creationdate("2023:05:14 21:34:06Z"),
+        Some((
+            String::from("2023-05-14 21:34:06"),
+            FixedOffset::east_opt(0)
+        ))
+    );
+}
+
+#[test]
+fn creationdate_invalid() {
+    assert_eq!(creationdate(""), None);
+    assert_eq!(creationdate("2023:05:14"), None);
+    assert_eq!(creationdate("2023:05:14 21:34:06-5"), None);
+    assert_eq!(creationdate("2023:05:14 21:34:06-05:00:00"), None);
+    assert_eq!(creationdate("0000:00:00 00:00:00"), None);
+}
+
+#[test]
+fn dash_separated_date_without_offset() {
+    let parts = parse_datetime_with_offset("2023-05-14 21:34:06").unwrap();
+
+    assert_eq!(
+        parts.datetime,
+        NaiveDate::from_ymd_opt(2023, 5, 14)
+            .unwrap()
+            .and_hms_opt(21, 34, 6)
+            .unwrap()
+    );
+    assert_eq!(parts.offset, None);
+}
@@ -0,0 +1,162 @@
+use chrono::FixedOffset;
+use ios2exif::{
+    format_datetime, get_all_timestamps, get_timestamp_and_rename_pair, get_timestamp_from_exif,
+    ExiftoolSession, MediaKind, Options, TimestampError, DEFAULT_FORMAT,
+};
+use std::{env, fs, path::PathBuf, process};
+
+// The sample files in "tests/fixtures"
+fn fixture(name: &str) -> (PathBuf, String) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    let path_str = path.to_string_lossy().to_string();
+    (path, path_str)
+}
+
+#[test]
+fn jpeg() {
+    let (path, path_str) = fixture("datetimeoriginal.jpg");
+    let parts = get_timestamp_from_exif(&path, &path_str).unwrap();
+
+    assert_eq!(parts.raw, "2023:05:14 21:34:06");
+    assert_eq!(parts.subsec.as_deref(), Some("123"));
+    assert_eq!(parts.offset, FixedOffset::west_opt(5 * 3600));
+    assert_eq!(
+        format_datetime(&parts, DEFAULT_FORMAT),
+        "2023-05-14_21-34-06-123"
+    );
+}
+
+#[test]
+fn combined_subsec_and_offset() {
+    // DateTimeOriginal is "2023:05:25 19:47:30.45+02:00", with SubSecTimeOriginal "999" and OffsetTimeOriginal "-04:00"
+    let (path, path_str) = fixture("subsecdatetimeoriginal.jpg");
+    let parts = get_timestamp_from_exif(&path, &path_str).unwrap();
+
+    assert_eq!(parts.raw, "2023:05:25 19:47:30.45+02:00");
+    assert_eq!(parts.subsec.as_deref(), Some("450"));
+    assert_eq!(parts.offset, FixedOffset::east_opt(2 * 3600));
+    assert_eq!(
+        format_datetime(&parts, DEFAULT_FORMAT),
+        "2023-05-25_19-47-30-450"
+    );
+}
+
+#[test]
+fn heic() {
+    let (path, path_str) = fixture("datetimeoriginal.heic");
+    let parts = get_timestamp_from_exif(&path, &path_str).unwrap();
+
+    assert_eq!(parts.subsec, None);
+    assert_eq!(parts.offset, FixedOffset::east_opt(2 * 3600));
+    assert_eq!(
+        format_datetime(&parts, DEFAULT_FORMAT),
+        "2023-05-25_19-47-30"
+    );
+    assert_eq!(format_datetime(&parts, "%Y%m%d-%H%M%S"), "20230525-194730");
+}
+
+#[test]
+fn large_heic() {
+    let (fixture, _) = fixture("datetimeoriginal.heic");
+    let heic = fs::read(fixture).unwrap();
+    // Bigger than what's read of a HEIC photo, which is only its "meta" box (right after the 24 byte "ftyp" box)
+    let image = vec![0; 20 * 1024 * 1024];
+    let path = env::temp_dir().join(format!("ios2exif-{}-large.heic", process::id()));
+    let path_str = path.to_string_lossy().to_string();
+
+    // The image data follows in an "mdat" box, which is skipped
+    let mut contents = heic.clone();
+    contents.extend_from_slice(&((image.len() + 8) as u32).to_be_bytes());
+    contents.extend_from_slice(b"mdat");
+    contents.extend_from_slice(&image);
+    fs::write(&path, &contents).unwrap();
+    let parts = get_timestamp_from_exif(&path, &path_str).unwrap();
+    assert_eq!(parts.raw, "2023:05:25 19:47:30");
+
+    // A "meta" box that claims to go on until the end of the file isn't read all the way
+    let mut contents = heic;
+    contents[24..28].copy_from_slice(&0u32.to_be_bytes());
+    contents.extend_from_slice(&image);
+    fs::write(&path, &contents).unwrap();
+    assert!(matches!(
+        get_timestamp_from_exif(&path, &path_str),
+        Err(TimestampError::InvalidExif { .. })
+    ));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn all_timestamps() {
+    let (path, path_str) = fixture("datetimeoriginal.jpg");
+    let session = ExiftoolSession::new(&PathBuf::from("exiftool-is-not-needed"));
+    let timestamps = get_all_timestamps(&path, &path_str, &Options::default(), &session, false);
+    let sources: Vec<&str> = timestamps
+        .iter()
+        .map(|(source, _)| source.as_str())
+        .collect();
+
+    // Nothing is picked, and exiftool isn't asked without being available
+    assert_eq!(
+        sources,
+        [
+            "EXIF DateTimeOriginal",
+            "EXIF DateTimeDigitized",
+            "EXIF GPS time",
+            "QuickTime movie header",
+            "modification time"
+        ]
+    );
+    assert_eq!(timestamps[0].1.as_ref().unwrap().raw, "2023:05:14 21:34:06");
+    assert!(matches!(
+        timestamps[1].1,
+        Err(TimestampError::MissingTag { .. })
+    ));
+    assert!(timestamps[4].1.is_ok());
+}
+
+#[test]
+fn without_exif() {
+    let (path, path_str) = fixture("no-exif.jpg");
+
+    assert!(matches!(
+        get_timestamp_from_exif(&path, &path_str),
+        Err(TimestampError::InvalidExif { .. })
+    ));
+}
+
+#[test]
+fn missing_file() {
+    let (path, path_str) = fixture("missing.jpg");
+
+    assert!(matches!(
+        get_timestamp_from_exif(&path, &path_str),
+        Err(TimestampError::FileOpen { .. })
+    ));
+}
+
+// Every EXIF tag fails the same way for a file without EXIF, which is only reported once
+#[test]
+fn without_exif_reported_once() {
+    let (path, path_str) = fixture("no-exif.jpg");
+    let session = ExiftoolSession::new("exiftool".as_ref());
+    let errors = get_timestamp_and_rename_pair(
+        &path,
+        &path_str,
+        MediaKind::Photo,
+        &Options::default(),
+        None,
+        &session,
+        false,
+    )
+    .unwrap_err();
+
+    let invalid = errors
+        .iter()
+        .filter(|error| matches!(error, TimestampError::InvalidExif { .. }))
+        .count();
+    assert_eq!(invalid, 1);
+}
@@ -0,0 +1,365 @@
+use ios2exif::{
+    get_timestamp_and_rename_pair, read_tags_batch, CachedTagReader, ExiftoolCache, ExiftoolTags,
+    MediaKind, Options, TagReader, TimestampError, Timezone,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+// Stands in for exiftool, answering with canned output (and nothing for any other tag)
+#[derive(Default)]
+struct FakeExiftool {
+    output: HashMap<&'static str, &'static [u8]>,
+    // Every tag asked for, in order
+    requested: RefCell<Vec<String>>,
+}
+
+impl FakeExiftool {
+    fn new(output: &[(&'static str, &'static [u8])]) -> Self {
+        Self {
+            output: output.iter().copied().collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl TagReader for FakeExiftool {
+    fn read_tag(&self, tag: &str, _path: &Path) -> io::Result<Vec<u8>> {
+        self.requested.borrow_mut().push(tag.to_string());
+        Ok(self.output.get(tag).copied().unwrap_or_default().to_vec())
+    }
+}
+
+// Stands in for exiftool failing to run at all
+struct BrokenExiftool;
+
+impl TagReader for BrokenExiftool {
+    fn read_tag(&self, _tag: &str, _path: &Path) -> io::Result<Vec<u8>> {
+        Err(io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+// Reads a tag through the cache, returning the value and how many times exiftool was run so far
+fn read_cached(reader: &CachedTagReader<FakeExiftool>, tag: &str, path: &Path) -> (String, usize) {
+    let value = reader.read_tag(tag, path).unwrap();
+    (
+        String::from_utf8(value).unwrap().trim_end().to_string(),
+        reader.reader.requested.borrow().len(),
+    )
+}
+
+// Returns (timestamp, source) of a video, which doesn't need to exist as everything comes from exiftool
+fn video(exiftool: &dyn TagReader) -> Result<(String, String), Vec<TimestampError>> {
+    let options = Options {
+        timezone: Timezone::Utc,
+        ..Options::default()
+    };
+    let path = PathBuf::from("video.mov");
+    let (timestamp, _, source, _, _) = get_timestamp_and_rename_pair(
+        &path,
+        "video.mov",
+        MediaKind::QuickTime,
+        &options,
+        None,
+        exiftool,
+        true,
+    )?;
+
+    Ok((timestamp, source))
+}
+
+#[test]
+fn creationdate() {
+    let exiftool = FakeExiftool::new(&[("CreationDate", b"2023:05:14 21:34:06-05:00\n")]);
+
+    assert_eq!(
+        video(&exiftool).unwrap(),
+        (
+            String::from("2023-05-15_02-34-06"),
+            String::from("exiftool CreationDate")
+        )
+    );
+    assert_eq!(*exiftool.requested.borrow(), ["CreationDate"]);
+}
+
+#[test]
+fn creationdate_with_milliseconds() {
+    let exiftool = FakeExiftool::new(&[("CreationDate", b"2023:05:14 21:34:06.123-05:00\n")]);
+
+    // Clips recorded within the same second still get different names
+    assert_eq!(
+        video(&exiftool).unwrap(),
+        (
+            String::from("2023-05-15_02-34-06-123"),
+            String::from("exiftool CreationDate")
+        )
+    );
+}
+
+#[test]
+fn falls_back_to_createdate() {
+    let exiftool = FakeExiftool::new(&[("CreateDate", b"2023:05:14 21:34:06\r\n")]);
+
+    assert_eq!(
+        video(&exiftool).unwrap(),
+        (
+            String::from("2023-05-14_21-34-06"),
+            String::from("exiftool CreateDate")
+        )
+    );
+    assert_eq!(*exiftool.requested.borrow(), ["CreationDate", "CreateDate"]);
+}
+
+#[test]
+fn invalid_date_falls_through() {
+    let exiftool = FakeExiftool::new(&[
+        ("CreationDate", b"0000:00:00 00:00:00+00:00\n"),
+        ("CreateDate", b"not a date\n"),
+        ("MediaCreateDate", b"2023:05:14 21:34:06\n"),
+    ]);
+
+    assert_eq!(
+        video(&exiftool).unwrap(),
+        (
+            String::from("2023-05-14_21-34-06"),
+            String::from("exiftool MediaCreateDate")
+        )
+    );
+}
+
+// The CreateDate of photos is their local time (like the EXIF one), so the name isn't marked as UTC
+#[test]
+fn photo_createdate_is_local() {
+    let exiftool = FakeExiftool::new(&[("CreateDate", b"2023:05:14 21:34:06\n")]);
+    let path = PathBuf::from("photo.jpg");
+    let (timestamp, stem, source, _, _) = get_timestamp_and_rename_pair(
+        &path,
+        "photo.jpg",
+        MediaKind::Photo,
+        &Options::default(),
+        None,
+        &exiftool,
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(timestamp, "2023-05-14_21-34-06");
+    assert_eq!(stem, timestamp);
+    assert_eq!(source, "exiftool CreateDate");
+}
+
+#[test]
+fn no_tags() {
+    let errors = video(&FakeExiftool::default()).unwrap_err();
+
+    assert!(!errors.is_empty());
+    assert!(errors
+        .iter()
+        .all(|error| matches!(error, TimestampError::MissingTag { .. })));
+}
+
+#[test]
+fn invalid_utf8() {
+    let exiftool = FakeExiftool::new(&[("CreationDate", b"2023:05:14 \xff\n")]);
+    let errors = video(&exiftool).unwrap_err();
+
+    assert!(matches!(
+        errors[0],
+        TimestampError::Utf8 { ref tag, .. } if tag == "CreationDate"
+    ));
+}
+
+#[test]
+fn exiftool_failing() {
+    let errors = video(&BrokenExiftool).unwrap_err();
+
+    assert!(errors
+        .iter()
+        .all(|error| matches!(error, TimestampError::ExiftoolFailed { .. })));
+}
+
+// A tag the batch doesn't have is still read on its own
+#[test]
+fn missing_from_batch() {
+    let exiftool = FakeExiftool::new(&[("CreationDate", b"2023:05:14 21:34:06-05:00\n")]);
+    let batch = ExiftoolTags::from([(String::from("CreateDate"), String::from("not a date"))]);
+    let path = PathBuf::from("video.mov");
+    let (timestamp, _, source, _, _) = get_timestamp_and_rename_pair(
+        &path,
+        "video.mov",
+        MediaKind::QuickTime,
+        &Options {
+            timezone: Timezone::Utc,
+            ..Options::default()
+        },
+        Some(&batch),
+        &exiftool,
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(timestamp, "2023-05-15_02-34-06");
+    assert_eq!(source, "exiftool CreationDate");
+    assert_eq!(*exiftool.requested.borrow(), ["CreationDate"]);
+}
+
+// exiftool outputs values that look like numbers as JSON numbers, which are kept as text
+#[cfg(unix)]
+#[test]
+fn batch_numbers() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let program = env::temp_dir().join(format!("ios2exif-{}-exiftool", process::id()));
+    fs::write(
+        &program,
+        "#!/bin/sh\ncat > /dev/null\necho '[{\"SourceFile\": \"video.mov\", \"DateCreated\": 2023, \"CreateDate\": \"2023:05:14 21:34:06\"}]'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let batch = read_tags_batch(
+        &program,
+        &[PathBuf::from("video.mov")],
+        &["DateCreated", "CreateDate"],
+    )
+    .unwrap();
+    fs::remove_file(&program).unwrap();
+
+    let tags = &batch[Path::new("video.mov")];
+    assert_eq!(tags["DateCreated"], "2023");
+    assert_eq!(tags["CreateDate"], "2023:05:14 21:34:06");
+}
+
+#[test]
+fn cache_checks_file() {
+    let directory = directory("cache-checks-file");
+    let video = directory.join("video.mov");
+    fs::write(&video, b"video").unwrap();
+    let cache = ExiftoolCache::load(&directory.join("exiftool.json"));
+    let reader = CachedTagReader {
+        reader: FakeExiftool::new(&[("CreateDate", b"2023:05:14 21:34:06\n")]),
+        cache: Some(&cache),
+    };
+
+    assert_eq!(
+        read_cached(&reader, "CreateDate", &video),
+        (String::from("2023:05:14 21:34:06"), 1)
+    );
+    assert_eq!(read_cached(&reader, "CreateDate", &video).1, 1);
+    // A tag the file doesn't have is cached too
+    assert_eq!(
+        read_cached(&reader, "DateCreated", &video),
+        (String::new(), 2)
+    );
+    assert_eq!(read_cached(&reader, "DateCreated", &video).1, 2);
+
+    // Anything else about the file is forgotten once its size changes
+    fs::write(&video, b"edited video").unwrap();
+    assert_eq!(read_cached(&reader, "CreateDate", &video).1, 3);
+    assert_eq!(cache.get(&video, "DateCreated"), None);
+
+    // Or its modification time
+    File::options()
+        .write(true)
+        .open(&video)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(read_cached(&reader, "CreateDate", &video).1, 4);
+    assert_eq!(read_cached(&reader, "CreateDate", &video).1, 4);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn cache_get_all() {
+    let directory = directory("cache-get-all");
+    let video = directory.join("video.mov");
+    fs::write(&video, b"video").unwrap();
+    let cache = ExiftoolCache::load(&directory.join("exiftool.json"));
+
+    cache.insert(&video, "CreateDate", String::from("2023:05:14 21:34:06"));
+    // Another tag would've been read by a batch, so it isn't enough yet
+    assert!(cache
+        .get_all(&video, &["CreateDate", "DateCreated"])
+        .is_none());
+
+    // The tags the file doesn't have are left out
+    cache.insert(&video, "DateCreated", String::new());
+    assert_eq!(
+        cache.get_all(&video, &["CreateDate", "DateCreated"]),
+        Some(ExiftoolTags::from([(
+            String::from("CreateDate"),
+            String::from("2023:05:14 21:34:06")
+        )]))
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn cache_rename() {
+    let directory = directory("cache-rename");
+    let (from, to) = (
+        directory.join("IMG_0001.MOV"),
+        directory.join("renamed.mov"),
+    );
+    fs::write(&from, b"video").unwrap();
+    let cache = ExiftoolCache::load(&directory.join("exiftool.json"));
+
+    cache.insert(&from, "CreateDate", String::from("2023:05:14 21:34:06"));
+    fs::rename(&from, &to).unwrap();
+    cache.rename(&from, &to);
+
+    assert_eq!(
+        cache.get(&to, "CreateDate"),
+        Some(String::from("2023:05:14 21:34:06"))
+    );
+    // A new file with the old name isn't taken for the renamed one
+    fs::write(&from, b"video").unwrap();
+    assert_eq!(cache.get(&from, "CreateDate"), None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn cache_file() {
+    let directory = directory("cache-file");
+    let video = directory.join("video.mov");
+    let path = directory.join("cache").join("exiftool.json");
+    fs::write(&video, b"video").unwrap();
+
+    let cache = ExiftoolCache::load(&path);
+    cache.insert(&video, "CreateDate", String::from("2023:05:14 21:34:06"));
+    cache.save().unwrap();
+    assert_eq!(
+        ExiftoolCache::load(&path).get(&video, "CreateDate"),
+        Some(String::from("2023:05:14 21:34:06"))
+    );
+
+    // A cache file written by another version is thrown away
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::write(&path, contents.replace("\"version\":1", "\"version\":0")).unwrap();
+    assert_eq!(ExiftoolCache::load(&path).get(&video, "CreateDate"), None);
+
+    // And so is one that can't be read
+    fs::write(&path, b"not json").unwrap();
+    assert_eq!(ExiftoolCache::load(&path).get(&video, "CreateDate"), None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
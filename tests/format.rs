@@ -0,0 +1,123 @@
+use chrono::NaiveDate;
+use ios2exif::{
+    format_datetime, format_dup_suffix, format_with_separators, strip_dup_suffix,
+    validate_dup_format, validate_format, validate_template, DateTimeParts, DEFAULT_DUP_FORMAT,
+    DEFAULT_FORMAT,
+};
+
+#[test]
+fn default_format_is_valid() {
+    assert_eq!(validate_format(DEFAULT_FORMAT), Ok(()));
+    assert_eq!(
+        validate_format(&format_with_separators(".", ".", " ")),
+        Ok(())
+    );
+    assert_eq!(validate_format("%Y%m%d_%H%M%S%.3f"), Ok(()));
+    assert_eq!(validate_format("%F %-I%p, %A"), Ok(()));
+}
+
+#[test]
+fn illegal_literal_characters() {
+    for character in ['<', '>', ':', '"', '/', '\\', '|', '?', '*'] {
+        let format = format!("%Y-%m-%d{character}%H-%M-%S");
+        let error = validate_format(&format).unwrap_err();
+
+        assert!(
+            error.contains(&format!("{character:?}")),
+            "{format}: {error}"
+        );
+    }
+}
+
+#[test]
+fn specifiers_rendering_illegal_characters() {
+    for (format, token) in [
+        ("%T", "%T"),
+        ("%Y-%m-%d %R", "%R"),
+        ("%D", "%D"),
+        ("%x", "%x"),
+        ("%c", "%c"),
+        ("%Y %r", "%r"),
+    ] {
+        let error = validate_format(format).unwrap_err();
+
+        assert!(error.contains(&format!("\"{token}\"")), "{format}: {error}");
+    }
+}
+
+#[test]
+fn specifiers_needing_a_timezone() {
+    for format in ["%z", "%Y %:z", "%Z", "%+"] {
+        let error = validate_format(format).unwrap_err();
+
+        assert!(error.contains("timezone"), "{format}: {error}");
+    }
+}
+
+#[test]
+fn escaped_percent_is_allowed() {
+    assert_eq!(validate_format("%Y%%%m"), Ok(()));
+}
+
+#[test]
+fn invalid_specifiers() {
+    assert!(validate_format("%Y-%Q").is_err());
+    assert!(validate_format("%").is_err());
+}
+
+#[test]
+fn weekday_and_iso_week() {
+    let format = "%G-W%V-%a_%H-%M-%S";
+    let parts = DateTimeParts {
+        datetime: NaiveDate::from_ymd_opt(2023, 5, 25)
+            .and_then(|date| date.and_hms_opt(19, 47, 30))
+            .unwrap(),
+        subsec: None,
+        offset: None,
+        raw: String::new(),
+    };
+
+    assert_eq!(validate_format(format), Ok(()));
+    assert_eq!(format_datetime(&parts, format), "2023-W21-Thu_19-47-30");
+    assert_eq!(format_datetime(&parts, "%A %u"), "Thursday 4");
+}
+
+#[test]
+fn iso_week_needs_iso_year() {
+    let error = validate_format("%Y-W%V").unwrap_err();
+
+    assert!(error.contains("%G"), "{error}");
+    assert_eq!(validate_format("%Y-%m-%d_%G-W%V"), Ok(()));
+}
+
+#[test]
+fn templates() {
+    assert_eq!(validate_template("{date}_{make}-{camera}.{ext}"), Ok(()));
+    assert!(validate_template("{camera}")
+        .unwrap_err()
+        .contains("{date}"));
+    assert!(validate_template("{date}_{lens}")
+        .unwrap_err()
+        .contains("{lens}"));
+    assert!(validate_template("{date}_{camera").is_err());
+    assert!(validate_template("{date}/{camera}").is_err());
+}
+
+#[test]
+fn dup_formats() {
+    assert_eq!(validate_dup_format(DEFAULT_DUP_FORMAT), Ok(()));
+    assert_eq!(format_dup_suffix(DEFAULT_DUP_FORMAT, 2), " (2)");
+    assert_eq!(format_dup_suffix("_{n:03}", 2), "_002");
+    assert_eq!(format_dup_suffix("-dup{n}", 12), "-dup12");
+    assert_eq!(strip_dup_suffix("a_002", "_{n:03}"), Some("a"));
+    assert_eq!(strip_dup_suffix("a_02", "_{n:03}"), None);
+    assert_eq!(strip_dup_suffix("a (1)", DEFAULT_DUP_FORMAT), Some("a"));
+
+    // The number can't run into the timestamp, and there has to be exactly one
+    assert!(validate_dup_format("{n}").is_err());
+    assert!(validate_dup_format("1{n}").is_err());
+    assert!(validate_dup_format("_dup").is_err());
+    assert!(validate_dup_format("_{n}_{n}").is_err());
+    assert!(validate_dup_format("_{n:3}").is_err());
+    assert!(validate_dup_format("/{n}").is_err());
+}
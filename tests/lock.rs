@@ -0,0 +1,49 @@
+use ios2exif::{lock_directories, LOCK_FILE_NAME};
+use std::{env, fs, path::PathBuf, process, slice};
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn second_run() {
+    let directory = directory("second-run");
+    let lock = lock_directories(slice::from_ref(&directory), false).unwrap();
+    assert!(directory.join(LOCK_FILE_NAME).is_file());
+
+    let error = lock_directories(slice::from_ref(&directory), false).unwrap_err();
+    assert!(error.contains("already running"), "{error}");
+    // A file is locked through its folder
+    let file = directory.join("IMG_0001.HEIC");
+    assert!(lock_directories(&[file], false).is_err());
+
+    // Removed again once the run is done
+    drop(lock);
+    assert!(!directory.join(LOCK_FILE_NAME).exists());
+    assert!(lock_directories(slice::from_ref(&directory), false).is_ok());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn forced() {
+    let directory = directory("forced");
+    // Left behind by a run that was killed
+    fs::write(directory.join(LOCK_FILE_NAME), "1234\n").unwrap();
+
+    assert!(lock_directories(slice::from_ref(&directory), false).is_err());
+    let lock = lock_directories(slice::from_ref(&directory), true).unwrap();
+    assert_eq!(
+        fs::read_to_string(directory.join(LOCK_FILE_NAME)).unwrap(),
+        format!("{}\n", process::id())
+    );
+
+    drop(lock);
+    assert!(!directory.join(LOCK_FILE_NAME).exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,116 @@
+use ios2exif::{load_manifest, Manifest};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+// Writes the manifest into the directory and loads it
+fn load(directory: &Path, contents: &str) -> Result<Manifest, String> {
+    let path = directory.join("manifest.csv");
+    fs::write(&path, contents).unwrap();
+    load_manifest(&path)
+}
+
+#[test]
+fn seconds_and_milliseconds() {
+    let directory = directory("manifest-epochs");
+    let manifest = load(
+        &directory,
+        "IMG_0001.HEIC,1684100046\nIMG_0002.HEIC,1684100046123\n\"IMG_0003, copy.HEIC\",\"-86400\"\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        manifest.get(Path::new("IMG_0001.HEIC")),
+        Some(1_684_100_046_000)
+    );
+    assert_eq!(
+        manifest.get(Path::new("IMG_0002.HEIC")),
+        Some(1_684_100_046_123)
+    );
+    // Commas in the name don't need quoting, as the epoch comes last
+    assert_eq!(
+        manifest.get(Path::new("IMG_0003, copy.HEIC")),
+        Some(-86_400_000)
+    );
+    assert_eq!(manifest.get(Path::new("IMG_0004.HEIC")), None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn names_and_paths() {
+    let directory = directory("manifest-paths");
+    fs::create_dir_all(directory.join("2023")).unwrap();
+    fs::write(directory.join("2023").join("IMG_0002.HEIC"), b"").unwrap();
+    let manifest = load(
+        &directory,
+        "IMG_0001.HEIC,1684100046\n2023/IMG_0002.HEIC,1684100047\n2023/missing.HEIC,1684100048\n",
+    )
+    .unwrap();
+
+    // A name matches a file of that name in any folder
+    assert_eq!(
+        manifest.get(&directory.join("elsewhere").join("IMG_0001.HEIC")),
+        Some(1_684_100_046_000)
+    );
+    // A path is relative to the manifest, and only matches that file
+    assert_eq!(
+        manifest.get(&directory.join("2023").join("IMG_0002.HEIC")),
+        Some(1_684_100_047_000)
+    );
+    assert_eq!(manifest.get(Path::new("IMG_0002.HEIC")), None);
+    assert_eq!(manifest.get(Path::new("missing.HEIC")), None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn header_and_byte_order_mark() {
+    let directory = directory("manifest-header");
+    let manifest = load(
+        &directory,
+        "\u{feff}file,timestamp\n\nIMG_0001.HEIC,1684100046\n",
+    )
+    .unwrap();
+    assert_eq!(
+        manifest.get(Path::new("IMG_0001.HEIC")),
+        Some(1_684_100_046_000)
+    );
+
+    let manifest = load(&directory, "\u{feff}IMG_0001.HEIC,1684100046\n").unwrap();
+    assert_eq!(
+        manifest.get(Path::new("IMG_0001.HEIC")),
+        Some(1_684_100_046_000)
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn invalid_lines() {
+    let directory = directory("manifest-invalid");
+
+    // A first line naming a file is data, not a header
+    let error = load(&directory, "IMG_0001.HEIC,abc\n").unwrap_err();
+    assert!(error.contains("Line 1"), "{error}");
+
+    let error = load(
+        &directory,
+        "file,timestamp\nIMG_0001.HEIC,1684100046\nIMG_0002.HEIC\n",
+    )
+    .unwrap_err();
+    assert!(error.contains("Line 3"), "{error}");
+    assert!(load(&directory, "file,timestamp\n,1684100046\n").is_err());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
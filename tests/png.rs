@@ -0,0 +1,103 @@
+use ios2exif::read_png_date;
+
+// A PNG file with the given chunks as (type, data), the CRCs are left as zeros as they aren't checked
+fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+    for (kind, data) in chunks {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(*kind);
+        png.extend_from_slice(data);
+        png.extend_from_slice(&[0; 4]);
+    }
+
+    png.extend_from_slice(&0u32.to_be_bytes());
+    png.extend_from_slice(b"IEND");
+    png.extend_from_slice(&[0; 4]);
+    png
+}
+
+// An "iTXt" chunk with an empty language and translated keyword
+fn itxt(keyword: &str, compressed: bool, text: &str) -> Vec<u8> {
+    let mut data = keyword.as_bytes().to_vec();
+    data.extend_from_slice(&[0, u8::from(compressed), 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    data
+}
+
+fn read(png: &[u8]) -> Option<String> {
+    read_png_date(png).unwrap()
+}
+
+#[test]
+fn creation_time() {
+    let png = png(&[
+        (b"IHDR", &[0; 13]),
+        (b"tEXt", b"Creation Time\x002019:10:15 02:08:48"),
+    ]);
+
+    assert_eq!(read(&png).as_deref(), Some("2019:10:15 02:08:48"));
+}
+
+#[test]
+fn xmp() {
+    let element = itxt(
+        "XML:com.adobe.xmp",
+        false,
+        "<x:xmpmeta><photoshop:DateCreated>2019-10-15T02:08:48</photoshop:DateCreated></x:xmpmeta>",
+    );
+    let attribute = itxt(
+        "XML:com.adobe.xmp",
+        false,
+        "<rdf:Description xmp:CreateDate=\"2019-10-15T02:08:49\"/>",
+    );
+
+    assert_eq!(
+        read(&png(&[(b"iTXt", &element)])).as_deref(),
+        Some("2019-10-15T02:08:48")
+    );
+    assert_eq!(
+        read(&png(&[(b"iTXt", &attribute)])).as_deref(),
+        Some("2019-10-15T02:08:49")
+    );
+    // XMP wins over the "Creation Time", even if it comes later
+    assert_eq!(
+        read(&png(&[
+            (b"tEXt", b"Creation Time\x002000:01:01 00:00:00"),
+            (b"iTXt", &element)
+        ]))
+        .as_deref(),
+        Some("2019-10-15T02:08:48")
+    );
+}
+
+#[test]
+fn itxt_creation_time() {
+    let chunk = itxt("Creation Time", false, "2019-10-15T02:08:48");
+
+    assert_eq!(
+        read(&png(&[(b"iTXt", &chunk)])).as_deref(),
+        Some("2019-10-15T02:08:48")
+    );
+}
+
+// Compressed text is left to exiftool, the flag alone is enough to skip the chunk
+#[test]
+fn compressed_itxt() {
+    let chunk = itxt("Creation Time", true, "2019-10-15T02:08:48");
+
+    assert_eq!(read(&png(&[(b"iTXt", &chunk)])), None);
+}
+
+#[test]
+fn without_date() {
+    assert_eq!(read(&png(&[(b"IHDR", &[0; 13])])), None);
+    assert_eq!(read(&png(&[(b"tEXt", b"Software\0ios2exif")])), None);
+    // A text chunk without a keyword separator is skipped
+    assert_eq!(read(&png(&[(b"tEXt", b"Creation Time")])), None);
+}
+
+#[test]
+fn not_png() {
+    assert!(read_png_date(&b"\xFF\xD8\xFF\xE0 not a PNG"[..]).is_err());
+}
@@ -0,0 +1,102 @@
+use ios2exif::read_creation_time;
+use std::io::{Cursor, ErrorKind};
+
+// An atom with a 32 bit size
+fn atom(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut atom = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(contents);
+    atom
+}
+
+// An atom with a 64 bit size, which is what large "mdat" atoms use
+fn large_atom(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut atom = 1u32.to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(&((contents.len() + 16) as u64).to_be_bytes());
+    atom.extend_from_slice(contents);
+    atom
+}
+
+// A movie header of version 0 (32 bit times) or 1 (64 bit times), followed by some of its other fields
+fn mvhd(version: u8, creation_time: u64) -> Vec<u8> {
+    let mut contents = vec![version, 0, 0, 0];
+
+    if version == 1 {
+        contents.extend_from_slice(&creation_time.to_be_bytes());
+    } else {
+        contents.extend_from_slice(&(creation_time as u32).to_be_bytes());
+    }
+
+    contents.extend_from_slice(&[0; 20]);
+    atom(b"mvhd", &contents)
+}
+
+fn read(file: Vec<u8>) -> Option<u64> {
+    read_creation_time(Cursor::new(file)).unwrap()
+}
+
+// 2023-05-15 02:34:06 UTC
+const CREATION_TIME: u64 = 3_766_962_846;
+
+#[test]
+fn version_0() {
+    let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+    file.extend(atom(b"moov", &mvhd(0, CREATION_TIME)));
+
+    assert_eq!(read(file), Some(CREATION_TIME));
+}
+
+#[test]
+fn version_1() {
+    // Past 2040, which doesn't fit in 32 bits
+    let creation_time = u64::from(u32::MAX) + 1;
+    let mut file = atom(b"ftyp", b"mp42");
+    file.extend(atom(b"moov", &mvhd(1, creation_time)));
+
+    assert_eq!(read(file), Some(creation_time));
+}
+
+#[test]
+fn skipped_atoms() {
+    // The movie header isn't necessarily the first atom in "moov", or "moov" the first one in the file
+    let mut moov = atom(b"udta", &[0; 12]);
+    moov.extend(mvhd(0, CREATION_TIME));
+    let mut file = atom(b"ftyp", b"mp42");
+    file.extend(large_atom(b"mdat", &[0; 100]));
+    file.extend(large_atom(b"moov", &moov));
+
+    assert_eq!(read(file), Some(CREATION_TIME));
+
+    // A size of 0 runs until the end of the file
+    let mut file = atom(b"ftyp", b"mp42");
+    file.extend(0u32.to_be_bytes());
+    file.extend(b"moov");
+    file.extend(mvhd(0, CREATION_TIME));
+
+    assert_eq!(read(file), Some(CREATION_TIME));
+}
+
+#[test]
+fn without_creation_time() {
+    assert_eq!(read(atom(b"ftyp", b"mp42")), None);
+    assert_eq!(read(atom(b"moov", &atom(b"trak", &[0; 8]))), None);
+    // Never set
+    assert_eq!(read(atom(b"moov", &mvhd(0, 0))), None);
+}
+
+#[test]
+fn invalid_atom_size() {
+    // Smaller than its own header
+    let mut file = 4u32.to_be_bytes().to_vec();
+    file.extend(b"ftyp");
+    let error = read_creation_time(Cursor::new(file)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "invalid atom size");
+
+    // Bigger than the file (or its parent atom)
+    let mut moov = atom(b"moov", &mvhd(0, CREATION_TIME));
+    moov[..4].copy_from_slice(&1000u32.to_be_bytes());
+    let error = read_creation_time(Cursor::new(moov)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
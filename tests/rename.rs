@@ -0,0 +1,270 @@
+use glob::Pattern;
+use ios2exif::{apply_plan, plan_renames, undo_renames, CollisionPolicy, Options, Status};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process, slice,
+};
+
+// A JPEG with nothing but an EXIF DateTimeOriginal of format "YYYY:MM:DD HH:MM:SS"
+fn jpeg(datetime: &str) -> Vec<u8> {
+    let mut tiff = Vec::new();
+    // Big-endian header, IFD0 right after it
+    tiff.extend_from_slice(b"MM\0*\0\0\0\x08");
+    // IFD0 with a single entry pointing to the EXIF IFD at offset 26
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&[0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26]);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    // EXIF IFD with DateTimeOriginal, its value being stored right after the IFD at offset 44
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&[0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 44]);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(datetime.as_bytes());
+    tiff.push(0);
+
+    let length = (tiff.len() + 8) as u16;
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend_from_slice(&length.to_be_bytes());
+    jpeg.extend_from_slice(b"Exif\0\0");
+    jpeg.extend_from_slice(&tiff);
+    jpeg.extend_from_slice(&[0xFF, 0xD9]);
+    jpeg
+}
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn rename(directory: &Path) -> Vec<Status> {
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        ..Options::default()
+    };
+    let mut plan = plan_renames(&[directory.to_path_buf()], &options).unwrap();
+    apply_plan(&mut plan, &options).unwrap();
+
+    plan.entries.into_values().map(|info| info.status).collect()
+}
+
+#[test]
+fn swapped_names() {
+    let directory = directory("swapped-names");
+    let first = jpeg("2023:01:01 00:00:00");
+    let second = jpeg("2023:01:02 00:00:00");
+    fs::write(directory.join("2023-01-02_00-00-00.jpg"), &first).unwrap();
+    fs::write(directory.join("2023-01-01_00-00-00.jpg"), &second).unwrap();
+
+    let statuses = rename(&directory);
+
+    assert!(statuses.iter().all(|status| *status == Status::Renamed));
+    assert_eq!(
+        fs::read(directory.join("2023-01-01_00-00-00.jpg")).unwrap(),
+        first
+    );
+    assert_eq!(
+        fs::read(directory.join("2023-01-02_00-00-00.jpg")).unwrap(),
+        second
+    );
+    // Nothing is left at a temporary name
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn chained_names() {
+    let directory = directory("chained-names");
+    let first = jpeg("2023:01:02 00:00:00");
+    let second = jpeg("2023:01:03 00:00:00");
+    // "a.jpg" wants the name that "2023-01-02_00-00-00.jpg" is giving up
+    fs::write(directory.join("a.jpg"), &first).unwrap();
+    fs::write(directory.join("2023-01-02_00-00-00.jpg"), &second).unwrap();
+
+    let statuses = rename(&directory);
+
+    assert!(statuses.iter().all(|status| *status == Status::Renamed));
+    assert_eq!(
+        fs::read(directory.join("2023-01-02_00-00-00.jpg")).unwrap(),
+        first
+    );
+    assert_eq!(
+        fs::read(directory.join("2023-01-03_00-00-00.jpg")).unwrap(),
+        second
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn existing_destination_is_not_overwritten() {
+    let directory = directory("existing-destination");
+    let photo = jpeg("2023:01:01 00:00:00");
+    // Without any metadata, so it stays where it is
+    fs::write(directory.join("2023-01-01_00-00-00.jpg"), b"not a photo").unwrap();
+    fs::write(directory.join("a.jpg"), &photo).unwrap();
+
+    let statuses = rename(&directory);
+
+    // Caught while planning already, so it isn't even attempted
+    assert!(statuses.is_empty());
+    assert_eq!(fs::read(directory.join("a.jpg")).unwrap(), photo);
+    assert_eq!(
+        fs::read(directory.join("2023-01-01_00-00-00.jpg")).unwrap(),
+        b"not a photo"
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn existing_destination_gets_a_suffix() {
+    let directory = directory("existing-destination-suffix");
+    let photo = jpeg("2023:01:01 00:00:00");
+    fs::write(directory.join("2023-01-01_00-00-00.jpg"), b"not a photo").unwrap();
+    fs::write(directory.join("a.jpg"), &photo).unwrap();
+
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        collision_policy: CollisionPolicy::Suffix,
+        ..Options::default()
+    };
+    let mut plan = plan_renames(slice::from_ref(&directory), &options).unwrap();
+    apply_plan(&mut plan, &options).unwrap();
+
+    assert_eq!(
+        fs::read(directory.join("2023-01-01_00-00-00 (1).jpg")).unwrap(),
+        photo
+    );
+    assert_eq!(
+        fs::read(directory.join("2023-01-01_00-00-00.jpg")).unwrap(),
+        b"not a photo"
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn conflicts_are_skipped_with_keep_going() {
+    let directory = directory("keep-going");
+    fs::write(directory.join("a.jpg"), jpeg("2023:01:01 00:00:00")).unwrap();
+    fs::write(directory.join("b.jpg"), jpeg("2023:01:01 00:00:00")).unwrap();
+    fs::write(directory.join("c.jpg"), jpeg("2023:01:02 00:00:00")).unwrap();
+
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        keep_going: true,
+        ..Options::default()
+    };
+    let mut plan = plan_renames(slice::from_ref(&directory), &options).unwrap();
+    assert!(plan.has_conflicts);
+    // "b.jpg" was never planned, "a.jpg" doesn't get the name either
+    assert_eq!(plan.skip_conflicts(), 1);
+    apply_plan(&mut plan, &options).unwrap();
+
+    assert!(directory.join("a.jpg").is_file());
+    assert!(directory.join("b.jpg").is_file());
+    assert!(directory.join("2023-01-02_00-00-00.jpg").is_file());
+    assert!(!directory.join("2023-01-01_00-00-00.jpg").exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn only_matching_names_are_renamed() {
+    let directory = directory("pattern");
+    fs::write(directory.join("IMG_0001.JPG"), jpeg("2023:01:01 00:00:00")).unwrap();
+    fs::write(directory.join("DSC_0002.jpg"), jpeg("2023:01:02 00:00:00")).unwrap();
+    fs::write(directory.join("other.jpg"), jpeg("2023:01:03 00:00:00")).unwrap();
+
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        // Either of them is enough, regardless of the case
+        patterns: vec![
+            Pattern::new("img_*").unwrap(),
+            Pattern::new("DSC_*").unwrap(),
+        ],
+        ..Options::default()
+    };
+    let mut plan = plan_renames(slice::from_ref(&directory), &options).unwrap();
+    apply_plan(&mut plan, &options).unwrap();
+
+    assert!(directory.join("2023-01-01_00-00-00.jpg").is_file());
+    assert!(directory.join("2023-01-02_00-00-00.jpg").is_file());
+    assert!(directory.join("other.jpg").is_file());
+    assert!(!directory.join("2023-01-03_00-00-00.jpg").exists());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn missing_extension_is_sniffed() {
+    let directory = directory("missing-extension");
+    let photo = jpeg("2023:01:01 00:00:00");
+    fs::write(directory.join("IMG_0001"), &photo).unwrap();
+    // Neither a photo nor a video, so it's left alone
+    fs::write(directory.join("notes"), b"not a photo").unwrap();
+
+    let statuses = rename(&directory);
+
+    assert_eq!(statuses, [Status::Renamed]);
+    assert_eq!(
+        fs::read(directory.join("2023-01-01_00-00-00.jpg")).unwrap(),
+        photo
+    );
+    assert_eq!(fs::read(directory.join("notes")).unwrap(), b"not a photo");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn renames_are_undone_with_sidecars() {
+    let directory = directory("undo");
+    let photo = jpeg("2023:01:01 00:00:00");
+    fs::write(directory.join("IMG_0001.jpg"), &photo).unwrap();
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        sidecar: true,
+        ..Options::default()
+    };
+    let mut plan = plan_renames(slice::from_ref(&directory), &options).unwrap();
+    apply_plan(&mut plan, &options).unwrap();
+    assert!(directory.join("2023-01-01_00-00-00.jpg").is_file());
+
+    let summary = undo_renames(slice::from_ref(&directory), &options).unwrap();
+
+    assert_eq!(summary.renamed, 1);
+    assert_eq!(fs::read(directory.join("IMG_0001.jpg")).unwrap(), photo);
+    // The sidecar file is gone along with the new name
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+// Only Unix allows file names that aren't valid UTF-8
+#[cfg(unix)]
+#[test]
+fn no_sidecar_for_non_utf8_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let directory = directory("non-utf8-sidecar");
+    let original = directory.join(OsStr::from_bytes(b"IMG_\xFF.jpg"));
+    fs::write(&original, jpeg("2023:01:01 00:00:00")).unwrap();
+    let options = Options {
+        exiftool_path: PathBuf::from("exiftool-is-not-needed"),
+        sidecar: true,
+        ..Options::default()
+    };
+    let mut plan = plan_renames(slice::from_ref(&directory), &options).unwrap();
+    let summary = apply_plan(&mut plan, &options).unwrap();
+
+    // Renamed, but without a sidecar file that would send "undo" somewhere else
+    assert_eq!(summary.renamed, 1);
+    assert_eq!(summary.warnings, 1);
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,65 @@
+use ios2exif::LastRuns;
+use std::{
+    env, fs,
+    path::PathBuf,
+    process, slice,
+    time::{Duration, UNIX_EPOCH},
+};
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn since() {
+    let directory = directory("state-since");
+    let (first, second) = (directory.join("first"), directory.join("second"));
+    fs::create_dir_all(&first).unwrap();
+    fs::create_dir_all(&second).unwrap();
+    let mut runs = LastRuns::load(&directory.join("last-run.json")).unwrap();
+    let earlier = UNIX_EPOCH + Duration::from_millis(1_684_100_046_123);
+    let later = earlier + Duration::from_secs(60);
+
+    assert_eq!(runs.since(slice::from_ref(&first)), None);
+
+    runs.record(slice::from_ref(&first), earlier);
+    runs.record(slice::from_ref(&second), later);
+    assert_eq!(runs.since(slice::from_ref(&first)), Some(earlier));
+    // The earliest of them, so nothing changed since the older run is missed
+    assert_eq!(runs.since(&[first.clone(), second.clone()]), Some(earlier));
+    // The same folder however it's given
+    assert_eq!(runs.since(&[second.join("..").join("second")]), Some(later));
+
+    // A folder that wasn't run in before means every file is looked at
+    let new = directory.join("new");
+    assert_eq!(runs.since(&[first, second, new]), None);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn saved() {
+    let directory = directory("state-saved");
+    let path = directory.join("state").join("last-run.json");
+    let time = UNIX_EPOCH + Duration::from_millis(1_684_100_046_123);
+
+    let mut runs = LastRuns::load(&path).unwrap();
+    runs.record(slice::from_ref(&directory), time);
+    runs.save().unwrap();
+    assert_eq!(
+        LastRuns::load(&path)
+            .unwrap()
+            .since(slice::from_ref(&directory)),
+        Some(time)
+    );
+
+    fs::write(&path, b"not json").unwrap();
+    let error = LastRuns::load(&path).unwrap_err();
+    assert!(error.contains("corrupt"), "{error}");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
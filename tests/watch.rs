@@ -0,0 +1,74 @@
+use ios2exif::{watched_path, Options, Settling, SETTLE_TIME};
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant, SystemTime},
+};
+
+// An empty directory only used by a single test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("ios2exif-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn settling() {
+    let start = Instant::now();
+    let modified = Some(SystemTime::UNIX_EPOCH);
+    let mut file = Settling::new(start);
+
+    // The first check is a change, as nothing was known about the file yet
+    assert!(!file.has_settled(10, modified, start));
+    assert!(!file.has_settled(10, modified, start + SETTLE_TIME / 2));
+    assert!(file.has_settled(10, modified, start + SETTLE_TIME));
+
+    // Growing (or being touched) starts the wait over
+    let later = start + SETTLE_TIME;
+    assert!(!file.has_settled(20, modified, later));
+    assert!(!file.has_settled(20, modified, later + SETTLE_TIME - Duration::from_millis(1)));
+    let touched = modified.map(|modified| modified + Duration::from_secs(1));
+    assert!(!file.has_settled(20, touched, later + SETTLE_TIME));
+    assert!(file.has_settled(20, touched, later + SETTLE_TIME * 2));
+}
+
+#[test]
+fn watched_paths() {
+    let directory = directory("watched-paths");
+    fs::create_dir_all(directory.join("a").join("b")).unwrap();
+    for path in ["top.jpg", "a/middle.jpg", "a/b/bottom.jpg", ".hidden.jpg"] {
+        fs::write(directory.join(path), b"").unwrap();
+    }
+    let roots = [directory.clone()];
+    let options = Options::default();
+    let watched =
+        |path: &str, options: &Options| watched_path(&directory.join(path), &roots, options);
+
+    assert_eq!(
+        watched("top.jpg", &options),
+        Some(fs::canonicalize(directory.join("top.jpg")).unwrap())
+    );
+    assert!(watched("a/b/bottom.jpg", &options).is_some());
+    // Folders and hidden files aren't renamed, and neither is what's already gone
+    assert_eq!(watched("a", &options), None);
+    assert_eq!(watched(".hidden.jpg", &options), None);
+    assert_eq!(watched("missing.jpg", &options), None);
+
+    let options = Options {
+        max_depth: Some(1),
+        ..Options::default()
+    };
+    assert!(watched("top.jpg", &options).is_some());
+    assert!(watched("a/middle.jpg", &options).is_some());
+    assert_eq!(watched("a/b/bottom.jpg", &options), None);
+
+    // Outside of every watched folder
+    let other = env::temp_dir().join(format!("ios2exif-{}-unwatched.jpg", process::id()));
+    fs::write(&other, b"").unwrap();
+    assert_eq!(watched_path(&other, &roots, &Options::default()), None);
+
+    fs::remove_file(&other).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}